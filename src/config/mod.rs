@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer, de::Error};
+
+use crate::system::UserId;
+
+mod validate;
+pub use validate::ConfigError;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoProxyScope {
+    Global,
+    Server,
+    Channel
+}
+
+#[derive(Deserialize, Clone)]
+pub enum PresenceMode {
+    Online,
+    Busy,
+    Idle,
+    Invisible,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum AutoproxyConfig {
+    Member {
+        name: MemberName
+    },
+    Latch {
+        scope: AutoProxyScope,
+        timeout_seconds: u32,
+        presence_indicator: bool
+    },
+    Front {
+        presence_indicator: bool
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PluralkitConfig {
+    #[serde(deserialize_with = "parse_regex")]
+    pub message_pattern: Regex,
+    pub api_token: String,
+    #[serde(default = "default_pluralkit_api_url")]
+    pub api_url: String,
+}
+
+/// Where to reach Discord, for running behind a proxy. Defaults to Discord itself.
+#[derive(Deserialize, Clone, Default)]
+pub struct DiscordConfig {
+    pub api_url: Option<String>,
+    pub gateway_url: Option<String>,
+}
+
+/// Proxy through channel webhooks using a single listener bot, instead of a bot per member
+#[derive(Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub listener_token: String,
+}
+
+fn default_pluralkit_api_url() -> String {
+    "https://api.pluralkit.me/v2".to_string()
+}
+
+#[derive(Deserialize, Clone)]
+pub struct System {
+    pub reference_user_id: UserId,
+    pub members: Vec<Member>,
+    #[serde(default = "default_forward_pings")]
+    pub forward_pings: bool,
+    pub autoproxy: Option<AutoproxyConfig>,
+    pub pluralkit: Option<PluralkitConfig>,
+    pub ui_color: Option<String>,
+    #[serde(default = "default_data_directory")]
+    pub data_directory: String,
+    #[serde(default)]
+    pub discord: DiscordConfig,
+    pub webhook: Option<WebhookConfig>,
+    /// Member whose bot receives events for the whole system, the others connect only for presence
+    pub gateway_listener: Option<MemberName>,
+}
+
+fn default_forward_pings() -> bool {
+    false
+}
+
+fn default_data_directory() -> String {
+    "./data".to_string()
+}
+
+pub type MemberName = String;
+
+#[derive(Deserialize, Clone)]
+pub struct Member {
+    pub name: MemberName,
+    pub display_name: Option<String>,
+    #[serde(default, deserialize_with = "parse_optional_regex")]
+    pub message_pattern: Option<Regex>,
+    pub pluralkit_id: Option<String>,
+    pub discord_token: Option<String>,
+    pub avatar_url: Option<String>,
+    #[serde(skip)]
+    pub user_id: Option<UserId>,
+    pub presence: Option<PresenceMode>,
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(transparent)]
+pub struct Config {
+    pub systems: HashMap<String, System>
+}
+
+impl Config {
+    pub fn load(config_contents: String) -> Result<Config, Vec<ConfigError>> {
+        validate::validate(config_contents.as_str())
+    }
+}
+
+fn parse_regex<'de, D: Deserializer<'de>> (deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+
+    build_message_pattern(pattern)
+        .map_err(|e| D::Error::custom(e))
+}
+
+fn parse_optional_regex<'de, D: Deserializer<'de>> (deserializer: D) -> Result<Option<Regex>, D::Error> {
+    parse_regex(deserializer).map(Some)
+}
+
+pub fn build_message_pattern(mut pattern: String) -> Result<Regex, regex::Error> {
+    if !pattern.starts_with("^") {
+        pattern.insert(0, '^');
+    }
+
+    if !pattern.ends_with("$") {
+        pattern.push('$');
+    }
+
+    RegexBuilder::new(&pattern)
+        .dot_matches_new_line(true)
+        .case_insensitive(true)
+        .build()
+}
+
+/// Whether a capture group holds the message content: content itself, or content1, content2
+/// and so on for patterns with one group per alternative
+pub fn is_content_group(name: &str) -> bool {
+    name.strip_prefix("content")
+        .is_some_and(|suffix| suffix.is_empty() || suffix.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_autoproxy(autoproxy: &str) -> String {
+        format!(r#"
+            [system]
+            reference_user_id = "1"
+            {autoproxy}
+
+            [[system.members]]
+            name = "Alice"
+            message_pattern = "a:(?<content>.*)"
+            discord_token = "token"
+
+            [[system.members]]
+            name = "Bob"
+            message_pattern = "(?<content>.*)-b"
+            discord_token = "token"
+        "#)
+    }
+
+    fn load_system(source: String) -> System {
+        match Config::load(source) {
+            Ok(mut config) => config.systems.remove("system").unwrap(),
+            Err(errors) => panic!("Config should load: {errors:?}"),
+        }
+    }
+
+    fn load_errors(source: String) -> Vec<ConfigError> {
+        match Config::load(source) {
+            Ok(_) => panic!("Config should not load"),
+            Err(errors) => errors,
+        }
+    }
+
+    #[test]
+    fn loads_member_autoproxy_with_several_members() {
+        let system = load_system(config_with_autoproxy(r#"autoproxy = { mode = "member", name = "Bob" }"#));
+
+        assert!(matches!(system.autoproxy, Some(AutoproxyConfig::Member { name }) if name == "Bob"));
+        assert_eq!(system.members.len(), 2);
+    }
+
+    #[test]
+    fn rejects_unknown_autoproxy_member() {
+        let errors = load_errors(config_with_autoproxy(r#"autoproxy = { mode = "member", name = "Carol" }"#));
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].position, Some((4, 51)));
+        assert!(errors[0].message.contains("autoproxy member Carol"));
+    }
+
+    #[test]
+    fn autoproxy_member_lookup_is_exact() {
+        let errors = load_errors(config_with_autoproxy(r#"autoproxy = { mode = "member", name = "bob" }"#));
+        assert!(errors[0].message.contains("autoproxy member bob"));
+    }
+
+    #[test]
+    fn loads_latch_autoproxy() {
+        let system = load_system(config_with_autoproxy(
+            r#"autoproxy = { mode = "latch", scope = "server", timeout_seconds = 3600, presence_indicator = true }"#
+        ));
+
+        let Some(AutoproxyConfig::Latch { scope, timeout_seconds, presence_indicator }) = system.autoproxy else {
+            panic!("Expected latch autoproxy")
+        };
+
+        assert!(scope == AutoProxyScope::Server);
+        assert_eq!(timeout_seconds, 3600);
+        assert!(presence_indicator);
+    }
+
+    #[test]
+    fn rejects_unknown_latch_scope() {
+        let errors = load_errors(config_with_autoproxy(
+            r#"autoproxy = { mode = "latch", scope = "planet", timeout_seconds = 3600, presence_indicator = true }"#
+        ));
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("planet"));
+    }
+
+    #[test]
+    fn loads_front_autoproxy() {
+        let system = load_system(config_with_autoproxy(r#"autoproxy = { mode = "front", presence_indicator = false }"#));
+        assert!(matches!(system.autoproxy, Some(AutoproxyConfig::Front { presence_indicator: false })));
+    }
+
+    #[test]
+    fn loads_without_autoproxy() {
+        let system = load_system(config_with_autoproxy(""));
+
+        assert!(system.autoproxy.is_none());
+        assert!(!system.forward_pings);
+        assert_eq!(system.data_directory, "./data");
+        assert_eq!(system.members[1].name, "Bob");
+        assert!(system.members[1].display_name.is_none());
+    }
+
+    #[test]
+    fn normalizes_message_patterns() {
+        let system = load_system(config_with_autoproxy(""));
+        let pattern = system.members[0].message_pattern.as_ref().unwrap();
+
+        assert_eq!(pattern.as_str(), "^a:(?<content>.*)$");
+        assert!(pattern.is_match("A:hello\nthere"));
+        assert!(!pattern.is_match("oh a:hello"));
+
+        let pattern = build_message_pattern("^b:.*$".to_string()).unwrap();
+        assert_eq!(pattern.as_str(), "^b:.*$");
+    }
+
+    #[test]
+    fn recognizes_content_groups() {
+        assert!(is_content_group("content"));
+        assert!(is_content_group("content12"));
+        assert!(!is_content_group("contents"));
+        assert!(!is_content_group("content_warning"));
+        assert!(!is_content_group("context"));
+    }
+
+    #[test]
+    fn loads_gateway_listener() {
+        let system = load_system(config_with_autoproxy(r#"gateway_listener = "Alice""#));
+        assert_eq!(system.gateway_listener.as_deref(), Some("Alice"));
+    }
+
+    #[test]
+    fn rejects_unknown_gateway_listener() {
+        let errors = load_errors(config_with_autoproxy(r#"gateway_listener = "Carol""#));
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("gateway_listener Carol"));
+    }
+
+    const WEBHOOK_CONFIG: &str = r#"
+        [system]
+        reference_user_id = "1"
+
+        [system.webhook]
+        listener_token = "listener"
+
+        [[system.members]]
+        name = "Alice"
+        message_pattern = "a:(?<content>.*)"
+        avatar_url = "https://example.com/alice.png"
+    "#;
+
+    #[test]
+    fn loads_webhook_members_without_tokens() {
+        let system = load_system(WEBHOOK_CONFIG.to_string());
+
+        assert_eq!(system.webhook.unwrap().listener_token, "listener");
+        assert!(system.members[0].discord_token.is_none());
+        assert_eq!(system.members[0].avatar_url.as_deref(), Some("https://example.com/alice.png"));
+    }
+
+    #[test]
+    fn rejects_members_without_tokens_outside_webhook_mode() {
+        let errors = load_errors(r#"
+            [system]
+            reference_user_id = "1"
+
+            [[system.members]]
+            name = "Alice"
+            message_pattern = "a:(?<content>.*)"
+            avatar_url = "https://example.com/alice.png"
+        "#.to_string());
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("Member Alice has no discord_token"));
+    }
+}
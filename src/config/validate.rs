@@ -0,0 +1,334 @@
+use std::{collections::{HashMap, HashSet}, ops::Range};
+
+use serde::Deserialize;
+use toml::{Spanned, Value};
+
+use crate::system::UserId;
+use super::{build_message_pattern, is_content_group, Config};
+
+#[derive(Debug)]
+pub struct ConfigError {
+    /// Line and column, both starting at 1
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(source: &str, span: Option<Range<usize>>, message: String) -> Self {
+        Self {
+            position: span.map(|span| position(source, span.start)),
+            message,
+        }
+    }
+
+    fn from_toml(source: &str, error: toml::de::Error) -> Self {
+        Self::new(source, error.span(), error.message().to_string())
+    }
+}
+
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+// Loose mirrors of the config structs, keeping the spans of the values we check. Anything with
+// the wrong shape is left for the real deserialization to report.
+#[derive(Deserialize)]
+struct RawSystem {
+    reference_user_id: Option<Spanned<Value>>,
+    #[serde(default)]
+    members: Vec<RawMember>,
+    autoproxy: Option<RawAutoproxy>,
+    pluralkit: Option<RawPluralkit>,
+    webhook: Option<Value>,
+    gateway_listener: Option<Spanned<Value>>,
+}
+
+#[derive(Deserialize)]
+struct RawMember {
+    name: Option<Spanned<Value>>,
+    message_pattern: Option<Spanned<Value>>,
+    discord_token: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct RawAutoproxy {
+    mode: Option<Value>,
+    name: Option<Spanned<Value>>,
+}
+
+#[derive(Deserialize)]
+struct RawPluralkit {
+    message_pattern: Option<Spanned<Value>>,
+}
+
+/// Parses and checks a config file, collecting every problem found rather than stopping at the first
+pub fn validate(source: &str) -> Result<Config, Vec<ConfigError>> {
+    let raw_systems = toml::from_str::<HashMap<String, RawSystem>>(source)
+        .map_err(|error| vec![ConfigError::from_toml(source, error)])?;
+
+    let mut errors = Vec::new();
+    for (system_name, system) in raw_systems.iter() {
+        check_system(source, system_name, system, &mut errors);
+    }
+
+    // Deserialization stops at its first problem, which the checks above may have reported already
+    let config = toml::from_str::<Config>(source);
+    if let Err(error) = &config {
+        let error = ConfigError::from_toml(source, error.clone());
+
+        if !errors.iter().any(|reported| reported.position == error.position) {
+            errors.push(error);
+        }
+    }
+
+    match config {
+        Ok(config) if errors.is_empty() => Ok(config),
+        _ => {
+            errors.sort_by_key(|error| error.position);
+            Err(errors)
+        },
+    }
+}
+
+fn check_system(source: &str, system_name: &str, system: &RawSystem, errors: &mut Vec<ConfigError>) {
+    if let Some((reference_user_id, span)) = string_value(&system.reference_user_id) {
+        if reference_user_id.parse::<UserId>().is_err() {
+            errors.push(ConfigError::new(source, Some(span),
+                format!("System {system_name} reference_user_id {reference_user_id} is not a valid Discord user ID")
+            ));
+        }
+    }
+
+    let mut member_names = HashSet::new();
+    for member in system.members.iter() {
+        let member_name = string_value(&member.name);
+
+        if let Some((name, span)) = &member_name {
+            // Members are matched case-insensitively in commands, so these would be ambiguous
+            if !member_names.insert(name.to_lowercase()) {
+                errors.push(ConfigError::new(source, Some(span.clone()),
+                    format!("System {system_name} has more than one member named {name}")
+                ));
+            }
+        }
+
+        // Only webhook mode can do without a bot for each member
+        if let (Some((name, span)), None, None) = (&member_name, &member.discord_token, &system.webhook) {
+            errors.push(ConfigError::new(source, Some(span.clone()),
+                format!("Member {name} has no discord_token, which is needed unless system {system_name} uses webhooks")
+            ));
+        }
+
+        // Without PluralKit there's nowhere to import proxy tags from
+        if let (Some((name, span)), None, None) = (&member_name, &member.message_pattern, &system.pluralkit) {
+            errors.push(ConfigError::new(source, Some(span.clone()),
+                format!("Member {name} has no message_pattern, which is needed unless system {system_name} imports proxy tags from PluralKit")
+            ));
+        }
+
+        if let Some((pattern, span)) = string_value(&member.message_pattern) {
+            let description = format!("Message pattern for member {}", member_name.map_or("", |(name, _)| name));
+            check_pattern(source, &description, pattern, span, true, errors);
+        }
+    }
+
+    if let Some(autoproxy) = &system.autoproxy {
+        if let (Some(Value::String(mode)), Some((name, span))) = (&autoproxy.mode, string_value(&autoproxy.name)) {
+            let known_member = system.members.iter()
+                .any(|member| string_value(&member.name).is_some_and(|(member_name, _)| member_name == name));
+
+            if mode == "member" && !known_member {
+                errors.push(ConfigError::new(source, Some(span),
+                    format!("System {system_name} autoproxy member {name} does not match a known member name")
+                ));
+            }
+        }
+    }
+
+    if let Some((name, span)) = string_value(&system.gateway_listener) {
+        let known_member = system.members.iter()
+            .any(|member| string_value(&member.name).is_some_and(|(member_name, _)| member_name == name));
+
+        if system.webhook.is_some() {
+            errors.push(ConfigError::new(source, Some(span),
+                format!("System {system_name} uses webhooks, which always have their own listener, so gateway_listener can't be set")
+            ));
+        } else if !known_member {
+            errors.push(ConfigError::new(source, Some(span),
+                format!("System {system_name} gateway_listener {name} does not match a known member name")
+            ));
+        }
+    }
+
+    if let Some((pattern, span)) = system.pluralkit.as_ref().and_then(|pluralkit| string_value(&pluralkit.message_pattern)) {
+        check_pattern(source, "PluralKit message pattern", pattern, span, false, errors);
+    }
+}
+
+fn check_pattern(source: &str, description: &str, pattern: &str, span: Range<usize>, needs_content: bool, errors: &mut Vec<ConfigError>) {
+    match build_message_pattern(pattern.to_string()) {
+        Err(err) => errors.push(ConfigError::new(source, Some(span),
+            format!("{description} is not a valid regex: {err}")
+        )),
+        Ok(regex) => {
+            let has_content = regex.capture_names().flatten().any(is_content_group);
+
+            if needs_content && !has_content {
+                errors.push(ConfigError::new(source, Some(span),
+                    format!("{description} has no capture group named content")
+                ));
+            }
+        },
+    }
+}
+
+fn string_value(value: &Option<Spanned<Value>>) -> Option<(&str, Range<usize>)> {
+    match value.as_ref().map(|value| (value.get_ref(), value.span())) {
+        Some((Value::String(string), span)) => Some((string.as_str(), span)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BROKEN_CONFIG: &str = r#"[system]
+reference_user_id = "not a snowflake"
+
+[[system.members]]
+name = "Alice"
+message_pattern = "a:(?<content>.*"
+discord_token = "token"
+
+[[system.members]]
+name = "alice"
+message_pattern = "b:.*"
+discord_token = "token"
+
+[system.pluralkit]
+message_pattern = "pk;["
+api_token = "token"
+"#;
+
+    #[test]
+    fn reports_every_problem_in_order() {
+        let Err(errors) = validate(BROKEN_CONFIG) else {
+            panic!("Config should not load")
+        };
+
+        let positions: Vec<_> = errors.iter().map(|error| error.position).collect();
+        assert_eq!(positions, vec![Some((2, 21)), Some((6, 19)), Some((10, 8)), Some((11, 19)), Some((15, 19))]);
+
+        assert!(errors[0].message.contains("not a valid Discord user ID"));
+        assert!(errors[1].message.contains("Message pattern for member Alice is not a valid regex"));
+        assert!(errors[2].message.contains("more than one member named alice"));
+        assert!(errors[3].message.contains("no capture group named content"));
+        assert!(errors[4].message.contains("PluralKit message pattern is not a valid regex"));
+    }
+
+    #[test]
+    fn reports_checked_and_deserialization_problems_together() {
+        let config = r#"[system]
+reference_user_id = "1"
+forward_pings = "yes"
+
+[[system.members]]
+name = "Alice"
+message_pattern = "a:(?<content>.*)"
+discord_token = "token"
+
+[[system.members]]
+name = "alice"
+message_pattern = "b:(?<content>.*)"
+discord_token = "token"
+"#;
+
+        let Err(errors) = validate(config) else {
+            panic!("Config should not load")
+        };
+
+        let positions: Vec<_> = errors.iter().map(|error| error.position).collect();
+        assert_eq!(positions, vec![Some((3, 17)), Some((11, 8))]);
+        assert!(errors[1].message.contains("more than one member named alice"));
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_position() {
+        let Err(errors) = validate("[system]\nreference_user_id = \n") else {
+            panic!("Config should not load")
+        };
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].position, Some((2, 21)));
+    }
+
+    #[test]
+    fn accepts_numbered_content_groups() {
+        let config = r#"[system]
+reference_user_id = "1"
+
+[[system.members]]
+name = "Alice"
+message_pattern = "a:(?<content>.*)|(?<content1>.*)-a"
+discord_token = "token"
+"#;
+
+        assert!(validate(config).is_ok());
+    }
+
+    #[test]
+    fn rejects_groups_that_only_start_with_content() {
+        let config = r#"[system]
+reference_user_id = "1"
+
+[[system.members]]
+name = "Alice"
+message_pattern = "a:(?<contents>.*)"
+discord_token = "token"
+"#;
+
+        let Err(errors) = validate(config) else {
+            panic!("Config should not load")
+        };
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("no capture group named content"));
+    }
+
+    #[test]
+    fn rejects_members_without_patterns_outside_pluralkit_import() {
+        let member = r#"
+[[system.members]]
+name = "Alice"
+discord_token = "token"
+"#;
+
+        let Err(errors) = validate(&format!("[system]\nreference_user_id = \"1\"\n{member}")) else {
+            panic!("Config should not load")
+        };
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].position, Some((5, 8)));
+        assert!(errors[0].message.contains("Member Alice has no message_pattern"));
+
+        let with_pluralkit = format!(r#"[system]
+reference_user_id = "1"
+
+[system.pluralkit]
+message_pattern = "pk;.*"
+api_token = "token"
+{member}"#);
+
+        assert!(validate(&with_pluralkit).is_ok());
+    }
+
+    #[test]
+    fn finds_line_and_column() {
+        assert_eq!(position("abc\ndéf\n", 0), (1, 1));
+        assert_eq!(position("abc\ndéf\n", 7), (2, 3));
+    }
+}
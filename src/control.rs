@@ -0,0 +1,211 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{fs::{DirBuilderExt, FileTypeExt, PermissionsExt}, net::{UnixListener, UnixStream}},
+    path::Path,
+    process,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use crate::{SystemState, UiState};
+
+/// A request read from the control socket, one JSON object per line
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ControlRequest {
+    Status,
+    Logs { system: Option<String>, limit: Option<usize> },
+    Reload { system: String },
+    Restart { system: String },
+    Shutdown { system: String },
+    SetLatch { system: String, scope: String, member: String },
+    ClearLatch { system: String, scope: String },
+}
+
+/// A request along with where to send the response
+pub type ControlMessage = (ControlRequest, mpsc::Sender<Value>);
+
+pub fn listen(path: &Path, sender: mpsc::Sender<ControlMessage>) -> io::Result<()> {
+    // Clear out the socket left behind by a previous run, but nothing else that happens to be there
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => match UnixStream::connect(path) {
+            Ok(_) => return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another running instance", path.display()),
+            )),
+            Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path)?,
+            Err(err) => return Err(err),
+        },
+        Ok(_) => return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists and is not a socket", path.display()),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(err),
+    }
+
+    let listener = bind_private(path)?;
+
+    thread::Builder::new()
+        .name("seance_control".to_string())
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                let _ = thread::Builder::new()
+                    .name("seance_control_connection".to_string())
+                    .spawn(move || handle_connection(stream, sender));
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Anyone who can connect can stop or reload systems, so the socket is bound in a directory only
+/// we can enter and made private before it's moved to where others could reach it
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    let file_name = path.file_name().ok_or(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} is not a file path", path.display()),
+    ))?;
+
+    let private_directory = path.with_file_name(format!(".{}.{}", file_name.to_string_lossy(), process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&private_directory)?;
+
+    let private_path = private_directory.join(file_name);
+    let bound = UnixListener::bind(&private_path).and_then(|listener| {
+        fs::set_permissions(&private_path, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&private_path, path)?;
+        Ok(listener)
+    });
+
+    let _ = fs::remove_file(&private_path);
+    let _ = fs::remove_dir(&private_directory);
+    bound
+}
+
+fn handle_connection(stream: UnixStream, sender: mpsc::Sender<ControlMessage>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue
+        }
+
+        let response = match serde_json::from_str::<ControlRequest>(&line) {
+            Err(err) => error(format!("Invalid request: {err}")),
+            Ok(request) => {
+                let (reply_sender, reply_receiver) = mpsc::channel();
+
+                if sender.send((request, reply_sender)).is_err() {
+                    return Ok(())
+                }
+
+                reply_receiver.recv_timeout(Duration::from_secs(5))
+                    .unwrap_or_else(|_| error("Timed out waiting for a response".to_string()))
+            },
+        };
+
+        writeln!(writer, "{response}")?;
+    }
+
+    Ok(())
+}
+
+pub fn ok(mut fields: Value) -> Value {
+    fields["ok"] = json!(true);
+    fields
+}
+
+pub fn error(message: String) -> Value {
+    json!({ "ok": false, "error": message })
+}
+
+pub fn status(ui_state: &UiState) -> Value {
+    let systems: Map<String, Value> = ui_state.systems.iter().map(|(name, state)| {
+        let mut system = match state {
+            SystemState::Running(members) => json!({
+                "state": "running",
+                "members": members.iter().map(|(name, member)| (name.clone(), json!({
+                    "connected": member.connected,
+                    "failed": member.failed,
+                    "autoproxied": member.autoproxied,
+                }))).collect::<Map<String, Value>>(),
+            }),
+            SystemState::Reloading => json!({ "state": "reloading" }),
+            SystemState::Restarting => json!({ "state": "restarting" }),
+            SystemState::Shutdown => json!({ "state": "shutdown" }),
+        };
+
+        system["latches"] = json!(ui_state.latches.get(name).cloned().unwrap_or_default());
+        (name.clone(), system)
+    }).collect();
+
+    ok(json!({ "systems": systems }))
+}
+
+pub fn logs(ui_state: &UiState, system: Option<&String>, limit: Option<usize>) -> Value {
+    let logs: Vec<Value> = ui_state.logs.iter()
+        .filter(|(system_name, _)| system.map_or(true, |system| system == system_name))
+        .map(|(system_name, message)| json!({ "system": system_name, "message": message }))
+        .collect();
+
+    let start = logs.len().saturating_sub(limit.unwrap_or(logs.len()));
+    ok(json!({ "logs": &logs[start..] }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn socket_path(test_name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("seance-control-{}-{test_name}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn refuses_to_replace_other_files() {
+        let path = socket_path("regular-file");
+        fs::write(&path, "important").unwrap();
+
+        let err = listen(&path, mpsc::channel().0).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "important");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn leaves_a_running_instance_alone() {
+        let path = socket_path("live-socket");
+        let _running = UnixListener::bind(&path).unwrap();
+
+        let err = listen(&path, mpsc::channel().0).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(UnixStream::connect(&path).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replaces_stale_socket_with_a_private_one() {
+        let path = socket_path("stale-socket");
+        drop(UnixListener::bind(&path).unwrap());
+
+        listen(&path, mpsc::channel().0).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(UnixStream::connect(&path).is_ok());
+
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(!path.with_file_name(format!(".{file_name}.{}", std::process::id())).exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,47 @@
+use serde_json::{json, Value};
+use std::{fs::OpenOptions, io::{self, LineWriter, Write}, path::Path, time::{SystemTime, UNIX_EPOCH}};
+use twilight_model::util::Timestamp;
+
+use crate::SystemUiEvent;
+
+/// Writes UI events out as JSON lines, for running without a terminal
+pub struct EventWriter {
+    output: Box<dyn Write>,
+}
+
+impl EventWriter {
+    pub fn stdout() -> Self {
+        Self { output: Box::new(io::stdout()) }
+    }
+
+    pub fn file(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { output: Box::new(LineWriter::new(file)) })
+    }
+
+    pub fn write(&mut self, system_name: &str, event: &SystemUiEvent) {
+        let mut line = event_json(event);
+        line["system"] = json!(system_name);
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as i64;
+        if let Ok(timestamp) = Timestamp::from_micros(now) {
+            line["timestamp"] = json!(timestamp.iso_8601().to_string());
+        }
+
+        // Nowhere left to report a failed write, so drop the line rather than stop the systems
+        let _ = writeln!(self.output, "{line}");
+        let _ = self.output.flush();
+    }
+}
+
+fn event_json(event: &SystemUiEvent) -> Value {
+    match event {
+        SystemUiEvent::SystemClose => json!({ "event": "system_close" }),
+        SystemUiEvent::MemberAutoproxy(member) => json!({ "event": "member_autoproxy", "member": member }),
+        SystemUiEvent::ScopeAutoproxy(scope, member) => json!({ "event": "scope_autoproxy", "scope": scope, "member": member }),
+        SystemUiEvent::GatewayDisconnect(member) => json!({ "event": "gateway_disconnect", "member": member }),
+        SystemUiEvent::GatewayConnect(member) => json!({ "event": "gateway_connect", "member": member }),
+        SystemUiEvent::GatewayFailed(member) => json!({ "event": "gateway_failed", "member": member }),
+        SystemUiEvent::LogLine(message) => json!({ "event": "log", "message": message }),
+    }
+}
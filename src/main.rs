@@ -1,19 +1,57 @@
 #![feature(str_split_whitespace_remainder)]
 
 mod config;
+mod control;
+mod headless;
 mod system;
-use crossterm::{cursor::{self, MoveTo}, terminal::{Clear, ClearType, DisableLineWrap, EnableLineWrap, EnterAlternateScreen, LeaveAlternateScreen}};
-use system::{Manager, SystemThreadCommand};
-use std::{collections::{HashMap, VecDeque}, fs, io::{self, Write}, sync::mpsc, thread::{self, sleep, JoinHandle}, time::Duration};
-use tokio::runtime;
+mod tui;
+use control::{ControlMessage, ControlRequest};
+use headless::EventWriter;
+use serde_json::Value;
+use system::{LatchScope, Manager, SystemEvent, SystemThreadCommand};
+use std::{collections::{BTreeMap, HashMap, VecDeque}, env, fs, path::PathBuf, process, sync::mpsc, thread::{self, JoinHandle}, time::Duration};
+use tokio::{runtime, sync::mpsc::{channel, Sender}};
+use tui::UiAction;
 
 pub struct UiState {
-    pub systems: HashMap<String, SystemState>,
-    pub logs: VecDeque<String>,
+    pub systems: BTreeMap<String, SystemState>,
+    pub latches: HashMap<String, BTreeMap<String, String>>,
+    pub logs: VecDeque<(String, String)>,
+    pub selected_system: usize,
+    pub selected_member: usize,
+    pub log_filter: Option<String>,
+    pub log_scroll: usize,
+}
+
+impl UiState {
+    pub fn push_log(&mut self, system_name: &str, log: String) {
+        if self.logs.len() == MAX_LOG {
+            let _ = self.logs.pop_front();
+        }
+
+        // Keep the view still while the user is scrolled back
+        if self.log_scroll > 0 && self.log_filter.as_deref().map_or(true, |filter| filter == system_name) {
+            self.log_scroll += 1;
+        }
+
+        self.logs.push_back((system_name.to_string(), log));
+    }
+
+    pub fn selected_system_name(&self) -> Option<&String> {
+        self.systems.keys().nth(self.selected_system)
+    }
+
+    pub fn filtered_logs(&self) -> impl Iterator<Item = &(String, String)> {
+        self.logs.iter().filter(|(system_name, _)| self.log_filter.as_ref().map_or(true, |filter| filter == system_name))
+    }
+
+    pub fn filtered_log_count(&self) -> usize {
+        self.filtered_logs().count()
+    }
 }
 
 pub enum SystemState {
-    Running(HashMap<String, MemberState>),
+    Running(BTreeMap<String, MemberState>),
     Reloading,
     Restarting,
     Shutdown,
@@ -21,238 +59,456 @@ pub enum SystemState {
 
 pub struct MemberState {
     pub connected: bool,
+    pub failed: bool,
     pub autoproxied: bool,
 }
 
 pub enum SystemUiEvent {
     SystemClose,
     MemberAutoproxy(Option<String>),
+    ScopeAutoproxy(String, Option<String>),
     GatewayDisconnect(String),
     GatewayConnect(String),
+    GatewayFailed(String),
     LogLine(String),
 }
 
 const MAX_LOG : usize = 1000;
+const CONFIG_PATH: &str = "./config.toml";
+
+struct RunningSystem {
+    handle: JoinHandle<SystemThreadCommand>,
+    sender: Sender<SystemEvent>,
+}
+
+struct Options {
+    check_config: bool,
+    headless: bool,
+    log_file: Option<PathBuf>,
+    control_socket: Option<PathBuf>,
+}
+
+impl Options {
+    fn parse() -> Result<Self, String> {
+        let mut options = Options {
+            check_config: false,
+            headless: false,
+            log_file: None,
+            control_socket: None,
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "check-config" => options.check_config = true,
+                "--headless" => options.headless = true,
+                "--log-file" => {
+                    let path = args.next().ok_or("--log-file needs a path")?;
+                    options.log_file = Some(PathBuf::from(path));
+                },
+                "--control-socket" => {
+                    let path = args.next().ok_or("--control-socket needs a path")?;
+                    options.control_socket = Some(PathBuf::from(path));
+                },
+                _ => return Err(format!("Unknown argument {arg}")),
+            }
+        }
+
+        if options.log_file.is_some() && !options.headless {
+            return Err("--log-file can only be used with --headless".to_string())
+        }
+
+        Ok(options)
+    }
+}
 
 fn main() {
-    let initial_config = fs::read_to_string("./config.toml").expect("Could not find config file");
-    let config = config::Config::load(initial_config.to_string());
+    let options = Options::parse().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        eprintln!("Usage: seance-rs [--headless [--log-file <path>]] [--control-socket <path>]");
+        eprintln!("       seance-rs check-config");
+        process::exit(2);
+    });
+
+    let initial_config = fs::read_to_string(CONFIG_PATH).expect("Could not find config file");
+    let config = match config::Config::load(initial_config.to_string()) {
+        Ok(config) => config,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", describe_config_error(&error));
+            }
+            process::exit(1);
+        },
+    };
+
+    if options.check_config {
+        println!("{CONFIG_PATH} is valid");
+        return
+    }
+
+    let mut event_writer = match (options.headless, &options.log_file) {
+        (false, _) => None,
+        (true, None) => Some(EventWriter::stdout()),
+        (true, Some(path)) => Some(EventWriter::file(path).expect("Could not open log file")),
+    };
 
     let (waker, waiter) = mpsc::channel::<(String, SystemUiEvent)>();
-    let mut join_handles = Vec::<(String, JoinHandle<_>)>::new();
+    let (control_sender, control_receiver) = mpsc::channel::<ControlMessage>();
+
+    if let Some(path) = &options.control_socket {
+        control::listen(path, control_sender).expect("Could not open control socket");
+    }
 
     let mut ui_state = UiState {
-        systems: HashMap::new(),
+        systems: BTreeMap::new(),
+        latches: HashMap::new(),
         logs: VecDeque::new(),
+        selected_system: 0,
+        selected_member: 0,
+        log_filter: None,
+        log_scroll: 0,
     };
 
-    for (system_name, system_config) in config.systems.iter() {
-        let handle = spawn_system(system_name, system_config.clone(), waker.clone());
+    let mut supervisor = Supervisor {
+        config,
+        initial_config,
+        waker,
+        running_systems: HashMap::new(),
+    };
 
-        let mut member_states = HashMap::new();
-        for member_name in system_config.members.iter() {
-            member_states.insert(member_name.name.clone(), MemberState {
-                connected: false,
-                autoproxied: false,
-            });
-        }
+    let system_configs: Vec<_> = supervisor.config.systems.iter()
+        .map(|(system_name, system_config)| (system_name.clone(), system_config.clone()))
+        .collect();
 
-        let system_state = SystemState::Running(member_states);
-        ui_state.systems.insert(system_name.clone(), system_state);
+    for (system_name, system_config) in system_configs {
+        supervisor.start_system(&system_name, system_config, &mut ui_state);
+    }
 
-        join_handles.push((system_name.clone(), handle));
+    if event_writer.is_none() {
+        tui::enter().unwrap();
     }
 
-    crossterm::execute!(io::stdout(), EnterAlternateScreen).unwrap();
-    crossterm::execute!(io::stdout(), DisableLineWrap).unwrap();
+    let mut redraw = true;
 
     loop {
-        // Wait for an event from one of the threads
-        let ui_event = waiter.recv_timeout(Duration::from_secs(30));
-
-        if let Ok((system_name, ui_event)) = ui_event {
-            let system_state = ui_state.systems.get_mut(&system_name).unwrap();
-            match system_state {
-                SystemState::Running(member_states) => match ui_event {
-                    // We will check for the join in a second
-                    SystemUiEvent::SystemClose => (),
-
-                    SystemUiEvent::MemberAutoproxy(member_name) => {
-                        member_states.iter_mut().for_each(|(_, member_state)| {
-                            member_state.autoproxied = false;
-                        });
-
-                        if let Some(member_name) = member_name {
-                            member_states.get_mut(&member_name).unwrap()
-                                .autoproxied = true;
-                        }
-                    },
-
-                    SystemUiEvent::GatewayDisconnect(member_name) => {
-                        member_states.get_mut(&member_name).unwrap()
-                            .connected = false;
-                    },
+        if let Some(event_writer) = event_writer.as_mut() {
+            // Nothing to draw or take input from, so just wait for the next event
+            if let Ok((system_name, ui_event)) = waiter.recv_timeout(Duration::from_millis(100)) {
+                event_writer.write(&system_name, &ui_event);
+                apply_ui_event(&mut ui_state, system_name, ui_event);
+            }
+        } else {
+            if redraw {
+                tui::draw(&ui_state, &supervisor.config).unwrap();
+                redraw = false;
+            }
 
-                    SystemUiEvent::GatewayConnect(member_name) => {
-                        member_states.get_mut(&member_name).unwrap()
-                            .connected = true;
+            // Waiting on input doubles as our tick, so keep it short enough that events feel live
+            match tui::poll_input(&mut ui_state, &supervisor.config, Duration::from_millis(50)).unwrap() {
+                UiAction::None => (),
+                UiAction::Redraw => redraw = true,
+                UiAction::Quit => break,
+                UiAction::Command(name, command) => {
+                    redraw = true;
 
-                    },
+                    if let Err(err) = supervisor.send_command(&name, command, &mut ui_state) {
+                        ui_state.push_log(&name, err);
+                    }
+                },
+                UiAction::Event(name, event) => {
+                    redraw = true;
 
-                    SystemUiEvent::LogLine(log) => {
-                        if log.len() == MAX_LOG {
-                            let _ = ui_state.logs.pop_front();
-                        }
+                    if let Err(err) = supervisor.send_event(&name, event) {
+                        ui_state.push_log(&name, err);
+                    }
+                },
+            }
+        }
 
-                        ui_state.logs.push_back(
-                            format!("{system_name:>8.8}: {log}")
-                        );
-                    },
+        while let Ok((request, reply)) = control_receiver.try_recv() {
+            redraw = true;
+            let _ = reply.send(supervisor.handle_control_request(request, &mut ui_state));
+        }
 
-                },
-                _ => (),
+        while let Ok((system_name, ui_event)) = waiter.try_recv() {
+            redraw = true;
+            if let Some(event_writer) = event_writer.as_mut() {
+                event_writer.write(&system_name, &ui_event);
             }
+            apply_ui_event(&mut ui_state, system_name, ui_event);
         }
 
-        // Just to make sure the join handle is updated by the time we check
-        sleep(Duration::from_millis(10));
+        let finished: Vec<String> = supervisor.running_systems.iter()
+            .filter(|(_, running)| running.handle.is_finished())
+            .map(|(name, _)| name.clone())
+            .collect();
 
-        if let Some(completed_index) = join_handles.iter().position(|(_, handle)| handle.is_finished()) {
-            let (name, next_join) = join_handles.swap_remove(completed_index);
+        let mut shutdown_all = false;
+        for name in finished {
+            redraw = true;
+            let running = supervisor.running_systems.remove(&name).unwrap();
+            shutdown_all |= !supervisor.handle_system_command(&name, running.handle.join(), &mut ui_state);
+        }
 
-            match next_join.join() {
-                Err(err) => {
-                    let _ = ui_state.systems.insert(name.clone(), SystemState::Shutdown);
-                    ui_state.logs.push_back(
-                        format!("Thread for system {} panicked!", name)
-                    );
+        if shutdown_all {
+            break;
+        }
+    }
 
-                    ui_state.logs.push_back(
-                        format!("{:?}", err)
-                    );
-                },
+    if event_writer.is_none() {
+        tui::leave().unwrap();
+    }
 
-                Ok(SystemThreadCommand::Restart) => {
-                    let _ = ui_state.systems.insert(name.clone(), SystemState::Restarting);
-                    ui_state.logs.push_back(
-                        format!("Thread for system {} requested restart", name)
-                    );
-                    if let Some((_, config)) = config.systems.iter().find(|(system_name, _)| name == **system_name) {
-                        let handle = spawn_system(&name, config.clone(), waker.clone());
-                        join_handles.push((name, handle));
+    if let Some(path) = &options.control_socket {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn describe_config_error(error: &config::ConfigError) -> String {
+    match error.position {
+        Some((line, column)) => format!("{CONFIG_PATH}:{line}:{column}: {}", error.message),
+        None => format!("{CONFIG_PATH}: {}", error.message),
+    }
+}
+
+fn apply_ui_event(ui_state: &mut UiState, system_name: String, ui_event: SystemUiEvent) {
+    match ui_event {
+        // We will check for the join in a second
+        SystemUiEvent::SystemClose => {
+            ui_state.latches.remove(&system_name);
+        },
+
+        SystemUiEvent::ScopeAutoproxy(scope, member_name) => {
+            let latches = ui_state.latches.entry(system_name).or_default();
+
+            if let Some(member_name) = member_name {
+                latches.insert(scope, member_name);
+            } else {
+                latches.remove(&scope);
+            }
+        },
+
+        SystemUiEvent::LogLine(log) => {
+            ui_state.push_log(&system_name, log);
+        },
+
+        member_event => {
+            let Some(SystemState::Running(member_states)) = ui_state.systems.get_mut(&system_name) else {
+                return
+            };
+
+            match member_event {
+                SystemUiEvent::MemberAutoproxy(member_name) => {
+                    member_states.iter_mut().for_each(|(_, member_state)| {
+                        member_state.autoproxied = false;
+                    });
+
+                    if let Some(member_state) = member_name.and_then(|member_name| member_states.get_mut(&member_name)) {
+                        member_state.autoproxied = true;
                     }
                 },
 
-                Ok(SystemThreadCommand::ShutdownSystem) => {
-                    let _ = ui_state.systems.insert(name.clone(), SystemState::Shutdown);
-                    ui_state.logs.push_back(
-                        format!("Thread for system {} requested shutdown", name)
-                    );
-                    continue;
+                SystemUiEvent::GatewayDisconnect(member_name) => {
+                    if let Some(member_state) = member_states.get_mut(&member_name) {
+                        member_state.connected = false;
+                    }
                 },
 
-                Ok(SystemThreadCommand::ReloadConfig) => {
-                    let _ = ui_state.systems.insert(name.clone(), SystemState::Reloading);
-                    ui_state.logs.push_back(
-                        format!("Thread for system {} requested config reload", name)
-                    );
-                    let config_file = if let Ok(config_file) = fs::read_to_string("./config.toml") {
-                        config_file
-                    } else {
-                        ui_state.logs.push_back(
-                            format!("Could not open config file, continuing with initial config")
-                        );
-                        initial_config.clone()
-                    };
-
-                    let updated_config = config::Config::load(config_file);
+                SystemUiEvent::GatewayConnect(member_name) => {
+                    if let Some(member_state) = member_states.get_mut(&member_name) {
+                        member_state.connected = true;
+                        member_state.failed = false;
+                    }
+                },
 
-                    if let Some((_, system_config)) = updated_config.systems.into_iter().find(|(system_name, _)| *name == *system_name) {
-                        let handle = spawn_system(&name, system_config, waker.clone());
-                        join_handles.push((name.clone(), handle));
-                    } else {
-                        ui_state.logs.push_back(
-                            format!("New config file but this system no longer exists, exiting.")
-                        );
-                        continue;
+                SystemUiEvent::GatewayFailed(member_name) => {
+                    if let Some(member_state) = member_states.get_mut(&member_name) {
+                        member_state.connected = false;
+                        member_state.failed = true;
                     }
                 },
-                Ok(SystemThreadCommand::ShutdownAll) => break,
+
+                _ => unreachable!(),
             }
+        },
+    }
+}
+
+struct Supervisor {
+    config: config::Config,
+    initial_config: String,
+    waker: mpsc::Sender<(String, SystemUiEvent)>,
+    running_systems: HashMap<String, RunningSystem>,
+}
+
+impl Supervisor {
+    /// Sends a command to a running system, or acts on it directly if the system is stopped
+    fn send_command(&mut self, name: &String, command: SystemThreadCommand, ui_state: &mut UiState) -> Result<(), String> {
+        if !ui_state.systems.contains_key(name) {
+            return Err(format!("No system named {}", name))
         }
 
-        update_ui(&ui_state, &config);
+        if self.running_systems.contains_key(name) {
+            self.send_event(name, SystemEvent::NewCommand(command))
+        } else {
+            self.handle_system_command(name, Ok(command), ui_state);
+            Ok(())
+        }
     }
 
-    crossterm::execute!(io::stdout(), EnableLineWrap).unwrap();
-    crossterm::execute!(io::stdout(), LeaveAlternateScreen).unwrap();
-}
+    fn send_event(&self, name: &String, event: SystemEvent) -> Result<(), String> {
+        let running = self.running_systems.get(name)
+            .ok_or(format!("System {} is not running", name))?;
 
-fn spawn_system(system_name : &String, system_config: config::System, waker: mpsc::Sender<(String, SystemUiEvent)>) -> JoinHandle<SystemThreadCommand> {
-    let name = system_name.clone();
-    let config = system_config.clone();
+        running.sender.try_send(event)
+            .map_err(|_| format!("Could not send command to system {}", name))
+    }
 
-    thread::Builder::new()
-        .name(format!("seance_{}", &name))
-        .spawn(move || -> _ {
-            let thread_local_runtime = runtime::Builder::new_current_thread().enable_all().build().unwrap();
-            let dup_waker = waker.clone();
+    fn handle_control_request(&mut self, request: ControlRequest, ui_state: &mut UiState) -> Value {
+        let result = match request {
+            ControlRequest::Status => return control::status(ui_state),
+            ControlRequest::Logs { system, limit } => return control::logs(ui_state, system.as_ref(), limit),
 
-            thread_local_runtime.block_on(async {
-                let mut system = Manager::new(name.clone(), config, waker);
-                system.start_clients().await;
-            });
+            ControlRequest::Reload { system } => self.send_command(&system, SystemThreadCommand::ReloadConfig, ui_state),
+            ControlRequest::Restart { system } => self.send_command(&system, SystemThreadCommand::Restart, ui_state),
+            ControlRequest::Shutdown { system } => self.send_command(&system, SystemThreadCommand::ShutdownSystem, ui_state),
 
-            let _ = dup_waker.send((name, SystemUiEvent::SystemClose));
-            SystemThreadCommand::Restart
-        }).unwrap()
-}
+            ControlRequest::SetLatch { system, scope, member } => scope.parse::<LatchScope>()
+                .and_then(|scope| self.send_event(&system, SystemEvent::SetLatch(scope, member))),
+            ControlRequest::ClearLatch { system, scope } => scope.parse::<LatchScope>()
+                .and_then(|scope| self.send_event(&system, SystemEvent::ClearLatch(scope))),
+        };
+
+        match result {
+            Ok(()) => control::ok(Value::Object(Default::default())),
+            Err(err) => control::error(err),
+        }
+    }
 
-fn update_ui(ui_state: &UiState, config: &config::Config) {
-    crossterm::execute!(io::stdout(), Clear(ClearType::FromCursorUp)).unwrap();
-    crossterm::execute!(io::stdout(), MoveTo(0, 0)).unwrap();
-
-    let (width, height) = crossterm::terminal::size().unwrap();
-
-    let status_lines = (ui_state.systems.len() * 2) + ui_state.systems.values().map(|system| match system {
-        SystemState::Running(members) => members.len(),
-        SystemState::Reloading => 1,
-        SystemState::Restarting => 1,
-        SystemState::Shutdown => 1,
-    } ).sum::<usize>() + 1;
-
-    let log_space = height as usize - status_lines - 1;
-    let log_height = ui_state.logs.len();
-
-    for (name, state) in ui_state.systems.iter() {
-        println!("{name}");
-        match state {
-            SystemState::Shutdown => println!("  - [System stopped]"),
-            SystemState::Reloading => println!("  - [System reloading]"),
-            SystemState::Restarting => println!("  - [System restarting]"),
-            SystemState::Running(members) => for (name, state) in members {
-                if !state.connected {
-                    println!("  - {name} (connecting)")
-                } else if state.autoproxied {
-                    println!("  - {name} (autoproxy)")
+    /// Acts on a command for a system that isn't running, either because its thread just exited
+    /// with it or because it was issued while the system was stopped.
+    ///
+    /// Returns false if the whole program should exit.
+    fn handle_system_command(&mut self, name: &String, command: thread::Result<SystemThreadCommand>, ui_state: &mut UiState) -> bool {
+        match command {
+            Err(err) => {
+                let _ = ui_state.systems.insert(name.clone(), SystemState::Shutdown);
+                self.log(name,
+                    format!("Thread for system {} panicked!", name)
+                );
+
+                self.log(name,
+                    format!("{:?}", err)
+                );
+            },
+
+            Ok(SystemThreadCommand::Restart) => {
+                let _ = ui_state.systems.insert(name.clone(), SystemState::Restarting);
+                self.log(name,
+                    format!("Thread for system {} requested restart", name)
+                );
+                if let Some(system_config) = self.config.systems.get(name).cloned() {
+                    self.start_system(name, system_config, ui_state);
+                }
+            },
+
+            Ok(SystemThreadCommand::ShutdownSystem) => {
+                let _ = ui_state.systems.insert(name.clone(), SystemState::Shutdown);
+                self.log(name,
+                    format!("Thread for system {} requested shutdown", name)
+                );
+            },
+
+            Ok(SystemThreadCommand::ReloadConfig) => {
+                let _ = ui_state.systems.insert(name.clone(), SystemState::Reloading);
+                self.log(name,
+                    format!("Thread for system {} requested config reload", name)
+                );
+                let config_file = if let Ok(config_file) = fs::read_to_string(CONFIG_PATH) {
+                    config_file
                 } else {
-                    println!("  - {name}")
+                    self.log(name,
+                        "Could not open config file, continuing with initial config".to_string()
+                    );
+                    self.initial_config.clone()
+                };
+
+                let updated_config = match config::Config::load(config_file) {
+                    Ok(updated_config) => updated_config,
+                    Err(errors) => {
+                        for error in errors {
+                            self.log(name, describe_config_error(&error));
+                        }
+
+                        // Don't take the system down over a typo, keep running what we had
+                        self.log(name,
+                            "Config file has errors, continuing with the previous config".to_string()
+                        );
+                        if let Some(system_config) = self.config.systems.get(name).cloned() {
+                            self.start_system(name, system_config, ui_state);
+                        }
+                        return true
+                    },
+                };
+
+                if let Some((_, system_config)) = updated_config.systems.into_iter().find(|(system_name, _)| *name == *system_name) {
+                    self.config.systems.insert(name.clone(), system_config.clone());
+                    self.start_system(name, system_config, ui_state);
+                } else {
+                    let _ = ui_state.systems.insert(name.clone(), SystemState::Shutdown);
+                    self.log(name,
+                        "New config file but this system no longer exists, exiting.".to_string()
+                    );
                 }
             },
+
+            Ok(SystemThreadCommand::ShutdownAll) => return false,
         }
 
-        println!("");
+        true
     }
 
-    println!("{:-<width$}", "", width = width as usize);
+    // Goes through the event channel like any other log line, so headless output sees it too
+    fn log(&self, system_name: &str, log: String) {
+        let _ = self.waker.send((system_name.to_string(), SystemUiEvent::LogLine(log)));
+    }
 
-    let range = if log_height <= log_space {
-        0..log_height
-    } else {
-        log_height - log_space .. log_height
-    };
+    fn start_system(&mut self, system_name: &String, system_config: config::System, ui_state: &mut UiState) {
+        let member_states = system_config.members.iter()
+            .map(|member| (member.name.clone(), MemberState {
+                connected: false,
+                failed: false,
+                autoproxied: false,
+            }))
+            .collect();
 
-    for line in ui_state.logs.range(range) {
-        println!("{line}");
+        let (handle, sender) = spawn_system(system_name, system_config, self.waker.clone());
+
+        ui_state.systems.insert(system_name.clone(), SystemState::Running(member_states));
+        self.running_systems.insert(system_name.clone(), RunningSystem { handle, sender });
     }
 }
+
+fn spawn_system(system_name : &String, system_config: config::System, waker: mpsc::Sender<(String, SystemUiEvent)>) -> (JoinHandle<SystemThreadCommand>, Sender<SystemEvent>) {
+    let name = system_name.clone();
+    let config = system_config.clone();
+    let (system_sender, system_receiver) = channel::<SystemEvent>(100);
+    let thread_sender = system_sender.clone();
+
+    let handle = thread::Builder::new()
+        .name(format!("seance_{}", &name))
+        .spawn(move || -> _ {
+            let thread_local_runtime = runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            let dup_waker = waker.clone();
+
+            let thread_command = thread_local_runtime.block_on(async {
+                let mut system: Manager = Manager::new(name.clone(), config, waker);
+                system.start_clients((thread_sender, system_receiver)).await
+            });
+
+            let _ = dup_waker.send((name, SystemUiEvent::SystemClose));
+            thread_command
+        }).unwrap();
+
+    (handle, system_sender)
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+
+use super::bot::{Bot, BotError};
+use super::types::*;
+use super::webhook::WebhookBackend;
+
+/// Everything `Manager` needs to do on the chat platform as one member. The twilight `Bot` and
+/// webhooks are the real implementations, tests swap in a recording one.
+pub trait ProxyBackend {
+    type Error: fmt::Debug + fmt::Display;
+
+    async fn set_status(&self, status: Status);
+    async fn set_nick(&self, server_id: ServerId, nick: String);
+
+    /// Fetches a message again and feeds it back through the message handler
+    async fn resend_message(&self, message_id: MessageId, channel_id: ChannelId);
+    async fn fetch_recent_channel_messages(&self, channel_id: ChannelId) -> Result<Vec<FullMessage>, Self::Error>;
+
+    async fn trigger_typing(&self, channel_id: ChannelId) -> Result<(), Self::Error>;
+    async fn send_message(&self, channel_id: ChannelId, content: &str) -> Result<FullMessage, Self::Error>;
+    async fn send_direct_message(&self, user_id: UserId, content: &str) -> Result<FullMessage, Self::Error>;
+
+    /// Sends a copy of the message with new content, keeping its reply, attachments and mentions
+    async fn duplicate_message(&self, message: &TwiMessage, content: &str) -> Result<TwiMessage, Self::Error>;
+    async fn edit_message(&self, channel_id: ChannelId, message_id: MessageId, new_content: String) -> Result<FullMessage, Self::Error>;
+    async fn delete_message(&self, channel_id: ChannelId, message_id: MessageId) -> Result<(), Self::Error>;
+
+    async fn react_message(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji) -> Result<(), Self::Error>;
+    async fn unreact_message(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji) -> Result<(), Self::Error>;
+    async fn remove_reaction(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji, user_id: UserId) -> Result<(), Self::Error>;
+}
+
+/// The backend a member proxies through, depending on whether the system uses webhooks
+pub enum Backend {
+    Bot(Box<Bot>),
+    Webhook(WebhookBackend),
+}
+
+impl ProxyBackend for Backend {
+    type Error = BotError;
+
+    async fn set_status(&self, status: Status) {
+        match self {
+            Backend::Bot(bot) => bot.set_status(status).await,
+            Backend::Webhook(webhook) => webhook.set_status(status).await,
+        }
+    }
+
+    async fn set_nick(&self, server_id: ServerId, nick: String) {
+        match self {
+            Backend::Bot(bot) => bot.set_nick(server_id, nick).await,
+            Backend::Webhook(webhook) => webhook.set_nick(server_id, nick).await,
+        }
+    }
+
+    async fn resend_message(&self, message_id: MessageId, channel_id: ChannelId) {
+        match self {
+            Backend::Bot(bot) => bot.resend_message(message_id, channel_id).await,
+            Backend::Webhook(webhook) => webhook.resend_message(message_id, channel_id).await,
+        }
+    }
+
+    async fn fetch_recent_channel_messages(&self, channel_id: ChannelId) -> Result<Vec<FullMessage>, BotError> {
+        match self {
+            Backend::Bot(bot) => bot.fetch_recent_channel_messages(channel_id).await,
+            Backend::Webhook(webhook) => webhook.fetch_recent_channel_messages(channel_id).await,
+        }
+    }
+
+    async fn trigger_typing(&self, channel_id: ChannelId) -> Result<(), BotError> {
+        match self {
+            Backend::Bot(bot) => bot.trigger_typing(channel_id).await,
+            Backend::Webhook(webhook) => webhook.trigger_typing(channel_id).await,
+        }
+    }
+
+    async fn send_message(&self, channel_id: ChannelId, content: &str) -> Result<FullMessage, BotError> {
+        match self {
+            Backend::Bot(bot) => bot.send_message(channel_id, content).await,
+            Backend::Webhook(webhook) => webhook.send_message(channel_id, content).await,
+        }
+    }
+
+    async fn send_direct_message(&self, user_id: UserId, content: &str) -> Result<FullMessage, BotError> {
+        match self {
+            Backend::Bot(bot) => bot.send_direct_message(user_id, content).await,
+            Backend::Webhook(webhook) => webhook.send_direct_message(user_id, content).await,
+        }
+    }
+
+    async fn duplicate_message(&self, message: &TwiMessage, content: &str) -> Result<TwiMessage, BotError> {
+        match self {
+            Backend::Bot(bot) => bot.duplicate_message(message, content).await,
+            Backend::Webhook(webhook) => webhook.duplicate_message(message, content).await,
+        }
+    }
+
+    async fn edit_message(&self, channel_id: ChannelId, message_id: MessageId, new_content: String) -> Result<FullMessage, BotError> {
+        match self {
+            Backend::Bot(bot) => bot.edit_message(channel_id, message_id, new_content).await,
+            Backend::Webhook(webhook) => webhook.edit_message(channel_id, message_id, new_content).await,
+        }
+    }
+
+    async fn delete_message(&self, channel_id: ChannelId, message_id: MessageId) -> Result<(), BotError> {
+        match self {
+            Backend::Bot(bot) => bot.delete_message(channel_id, message_id).await,
+            Backend::Webhook(webhook) => webhook.delete_message(channel_id, message_id).await,
+        }
+    }
+
+    async fn react_message(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji) -> Result<(), BotError> {
+        match self {
+            Backend::Bot(bot) => bot.react_message(channel_id, message_id, emoji).await,
+            Backend::Webhook(webhook) => webhook.react_message(channel_id, message_id, emoji).await,
+        }
+    }
+
+    async fn unreact_message(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji) -> Result<(), BotError> {
+        match self {
+            Backend::Bot(bot) => bot.unreact_message(channel_id, message_id, emoji).await,
+            Backend::Webhook(webhook) => webhook.unreact_message(channel_id, message_id, emoji).await,
+        }
+    }
+
+    async fn remove_reaction(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji, user_id: UserId) -> Result<(), BotError> {
+        match self {
+            Backend::Bot(bot) => bot.remove_reaction(channel_id, message_id, emoji, user_id).await,
+            Backend::Webhook(webhook) => webhook.remove_reaction(channel_id, message_id, emoji, user_id).await,
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
 use futures::future::join_all;
 use tokio::sync::RwLock;
@@ -10,16 +12,35 @@ use twilight_model::http::attachment::Attachment;
 
 use super::*;
 
+const WEBHOOK_NAME: &str = "Séance";
+
 pub struct Client {
     client: Arc<Mutex<TwiClient>>,
     bot_conf: Arc<RwLock<BotConfig>>,
+    webhooks: Mutex<HashMap<ChannelId, (WebhookId, String)>>,
+    // The parent channel of each thread seen, or None for channels that aren't threads
+    thread_parents: Mutex<HashMap<ChannelId, Option<ChannelId>>>,
 }
 
 impl Client {
-    pub fn new(discord_token: &String, bot_conf: &Arc<RwLock<BotConfig>>) -> Self {
+    pub fn new(discord_token: &String, discord_config: &crate::config::DiscordConfig, bot_conf: &Arc<RwLock<BotConfig>>) -> Self {
+        let mut client = TwiClient::builder().token(discord_token.clone());
+
+        // Twilight takes the proxy as a bare host, plus whether to use plain http
+        if let Some(api_url) = &discord_config.api_url {
+            let (host, use_http) = match api_url.strip_prefix("http://") {
+                Some(host) => (host, true),
+                None => (api_url.strip_prefix("https://").unwrap_or(api_url), false),
+            };
+
+            client = client.proxy(host.trim_end_matches('/').to_string(), use_http);
+        }
+
         Self {
-            client: Arc::new(Mutex::new(TwiClient::new(discord_token.clone()))),
+            client: Arc::new(Mutex::new(client.build())),
             bot_conf: bot_conf.clone(),
+            webhooks: Mutex::new(HashMap::new()),
+            thread_parents: Mutex::new(HashMap::new()),
         }
     }
 
@@ -104,37 +125,71 @@ impl Client {
     }
 
     pub async fn react_message(&self, channel_id: ChannelId, message_id: MessageId, react: &'_ RequestReactionType<'_>) -> Result<(), TwiError> {
-        let _ = self.client.lock().await.create_reaction(
+        self.client.lock().await.create_reaction(
             channel_id,
             message_id,
             react
-        ).await;
+        ).await?;
 
-        return Ok(())
+        Ok(())
     }
 
-    pub async fn edit_message(&self, channel_id: ChannelId, message_id: MessageId, new_content: String) -> Result<FullMessage, TwiError> {
-        Ok(self.client.lock().await.update_message(channel_id, message_id)
-            .content(Some(new_content.as_str())).expect("Invalid message contents")
-            .await.expect("Could not update message")
-            .model().await.unwrap())
+    pub async fn unreact_message(&self, channel_id: ChannelId, message_id: MessageId, react: &'_ RequestReactionType<'_>) -> Result<(), TwiError> {
+        self.client.lock().await.delete_current_user_reaction(
+            channel_id,
+            message_id,
+            react
+        ).await?;
+
+        Ok(())
     }
 
-    pub async fn duplicate_message(&self, message: &TwiMessage, content: &str) -> Result<TwiMessage, MessageDuplicateError> {
-        let client = self.client.lock().await;
+    pub async fn remove_reaction(&self, channel_id: ChannelId, message_id: MessageId, react: &'_ RequestReactionType<'_>, user_id: UserId) -> Result<(), TwiError> {
+        self.client.lock().await.delete_reaction(
+            channel_id,
+            message_id,
+            react,
+            user_id
+        ).await?;
 
-        let mut create_message = client.create_message(message.channel_id).content(content)?;
+        Ok(())
+    }
 
-        let mut allowed_mentions = AllowedMentions {
-            parse: Vec::new(),
-            replied_user: false,
-            roles: message.mention_roles.clone(),
-            users: message.mentions.iter().map(|user| user.id).collect(),
-        };
+    pub async fn trigger_typing(&self, channel_id: ChannelId) -> Result<(), TwiError> {
+        self.client.lock().await.create_typing_trigger(channel_id).await?;
 
-        if message.mention_everyone {
-            allowed_mentions.parse.push(MentionType::Everyone);
-        }
+        Ok(())
+    }
+
+    pub async fn send_message(&self, channel_id: ChannelId, content: &str) -> Result<FullMessage, BotError> {
+        Ok(self.client.lock().await.create_message(channel_id)
+            .content(content)?
+            .await?
+            .model().await?)
+    }
+
+    pub async fn send_direct_message(&self, user_id: UserId, content: &str) -> Result<FullMessage, BotError> {
+        let channel = self.client.lock().await
+            .create_private_channel(user_id)
+            .await?
+            .model().await?;
+
+        self.send_message(channel.id, content).await
+    }
+
+    pub async fn edit_message(&self, channel_id: ChannelId, message_id: MessageId, new_content: String) -> Result<FullMessage, BotError> {
+        Ok(self.client.lock().await.update_message(channel_id, message_id)
+            .content(Some(new_content.as_str()))?
+            .await?
+            .model().await?)
+    }
+
+    pub async fn duplicate_message(&self, message: &TwiMessage, content: &str) -> Result<TwiMessage, BotError> {
+        let attachments = copy_attachments(message).await;
+        let mut allowed_mentions = allowed_mentions(message);
+
+        let client = self.client.lock().await;
+        let mut create_message = client.create_message(message.channel_id).content(content)?;
 
         if message.kind == MessageType::Reply {
             if let Some(ref_message) = message.referenced_message.as_ref() {
@@ -155,32 +210,6 @@ impl Client {
             }
         }
 
-        let attachments = join_all(message.attachments.iter().map(|attachment| async {
-            let filename = attachment.filename.clone();
-            let description_opt = attachment.description.clone();
-            let bytes = reqwest::get(attachment.proxy_url.clone())
-                .await?
-                .bytes()
-                .await?;
-            let mut new_attachment =
-                Attachment::from_bytes(filename, bytes.try_into().unwrap(), attachment.id.into());
-
-            if let Some(description) = description_opt {
-                new_attachment.description(description);
-            }
-
-            Ok(new_attachment)
-        }))
-        .await
-        .iter()
-        .filter_map(
-            |result: &Result<Attachment, MessageDuplicateError>| match result {
-                Ok(attachment) => Some(attachment.clone()),
-                Err(_) => None,
-            },
-        )
-        .collect::<Vec<_>>();
-
         if attachments.len() > 0 {
             create_message = create_message.attachments(attachments.as_slice())?;
         }
@@ -194,36 +223,202 @@ impl Client {
 
         Ok(new_message)
     }
+
+    pub async fn execute_webhook(&self, message: &TwiMessage, content: &str, username: &str, avatar_url: Option<&str>) -> Result<TwiMessage, BotError> {
+        let (webhook_channel_id, thread_id) = self.webhook_target(message.channel_id).await?;
+        let (webhook_id, token) = self.channel_webhook(webhook_channel_id).await?;
+        let attachments = copy_attachments(message).await;
+        let allowed_mentions = allowed_mentions(message);
+
+        let client = self.client.lock().await;
+        let mut execute_webhook = client.execute_webhook(webhook_id, &token)
+            .content(content)?
+            .username(username)?
+            .allowed_mentions(Some(&allowed_mentions));
+
+        if let Some(avatar_url) = avatar_url {
+            execute_webhook = execute_webhook.avatar_url(avatar_url);
+        }
+
+        if !attachments.is_empty() {
+            execute_webhook = execute_webhook.attachments(attachments.as_slice())?;
+        }
+
+        if let Some(thread_id) = thread_id {
+            execute_webhook = execute_webhook.thread_id(thread_id);
+        }
+
+        Ok(execute_webhook.wait().await?.model().await?)
+    }
+
+    pub async fn edit_webhook_message(&self, channel_id: ChannelId, message_id: MessageId, new_content: String) -> Result<FullMessage, BotError> {
+        let (webhook_channel_id, thread_id) = self.webhook_target(channel_id).await?;
+        let (webhook_id, token) = self.channel_webhook(webhook_channel_id).await?;
+
+        let client = self.client.lock().await;
+        let mut update_message = client.update_webhook_message(webhook_id, &token, message_id)
+            .content(Some(new_content.as_str()))?;
+
+        if let Some(thread_id) = thread_id {
+            update_message = update_message.thread_id(thread_id);
+        }
+
+        Ok(update_message.await?.model().await?)
+    }
+
+    pub async fn fetch_webhook_message(&self, channel_id: ChannelId, message_id: MessageId) -> Result<FullMessage, BotError> {
+        let (webhook_channel_id, thread_id) = self.webhook_target(channel_id).await?;
+        let (webhook_id, token) = self.channel_webhook(webhook_channel_id).await?;
+
+        let client = self.client.lock().await;
+        let mut webhook_message = client.webhook_message(webhook_id, &token, message_id);
+
+        if let Some(thread_id) = thread_id {
+            webhook_message = webhook_message.thread_id(thread_id);
+        }
+
+        Ok(webhook_message.await?.model().await?)
+    }
+
+    /// Threads can't have webhooks, so messages go through their parent channel's webhook
+    /// with the thread id. Returns the webhook's channel and the thread, if any.
+    async fn webhook_target(&self, channel_id: ChannelId) -> Result<(ChannelId, Option<ChannelId>), BotError> {
+        let mut thread_parents = self.thread_parents.lock().await;
+
+        let parent_id = match thread_parents.get(&channel_id) {
+            Some(parent_id) => *parent_id,
+            None => {
+                let channel = self.client.lock().await.channel(channel_id).await?.model().await?;
+                let parent_id = if channel.kind.is_thread() { channel.parent_id } else { None };
+
+                thread_parents.insert(channel_id, parent_id);
+                parent_id
+            },
+        };
+
+        Ok(match parent_id {
+            Some(parent_id) => (parent_id, Some(channel_id)),
+            None => (channel_id, None),
+        })
+    }
+
+    /// Finds the webhook this bot made for the channel, creating one the first time
+    async fn channel_webhook(&self, channel_id: ChannelId) -> Result<(WebhookId, String), BotError> {
+        let mut webhooks = self.webhooks.lock().await;
+        if let Some(webhook) = webhooks.get(&channel_id) {
+            return Ok(webhook.clone())
+        }
+
+        let user_id = self.bot_conf.read().await.user_id;
+        let client = self.client.lock().await;
+
+        let existing = client.channel_webhooks(channel_id).await?.models().await?
+            .into_iter()
+            .find(|webhook| webhook.token.is_some() && webhook.user.as_ref().map(|user| user.id) == user_id);
+
+        let webhook = match existing {
+            Some(webhook) => webhook,
+            None => client.create_webhook(channel_id, WEBHOOK_NAME)?.await?.model().await?,
+        };
+
+        let webhook = (webhook.id, webhook.token.ok_or(BotError::MissingWebhookToken)?);
+        webhooks.insert(channel_id, webhook.clone());
+        Ok(webhook)
+    }
+}
+
+fn allowed_mentions(message: &TwiMessage) -> AllowedMentions {
+    let mut allowed_mentions = AllowedMentions {
+        parse: Vec::new(),
+        replied_user: false,
+        roles: message.mention_roles.clone(),
+        users: message.mentions.iter().map(|user| user.id).collect(),
+    };
+
+    if message.mention_everyone {
+        allowed_mentions.parse.push(MentionType::Everyone);
+    }
+
+    allowed_mentions
+}
+
+async fn copy_attachments(message: &TwiMessage) -> Vec<Attachment> {
+    join_all(message.attachments.iter().map(|attachment| async {
+        let filename = attachment.filename.clone();
+        let description_opt = attachment.description.clone();
+        let bytes = reqwest::get(attachment.proxy_url.clone())
+            .await?
+            .bytes()
+            .await?;
+        let mut new_attachment =
+            Attachment::from_bytes(filename, bytes.try_into().unwrap(), attachment.id.into());
+
+        if let Some(description) = description_opt {
+            new_attachment.description(description);
+        }
+
+        Ok(new_attachment)
+    }))
+    .await
+    .iter()
+    .filter_map(
+        |result: &Result<Attachment, BotError>| match result {
+            Ok(attachment) => Some(attachment.clone()),
+            Err(_) => None,
+        },
+    )
+    .collect::<Vec<_>>()
 }
 
 #[derive(Debug)]
-pub enum MessageDuplicateError {
+pub enum BotError {
     MessageValidation(twilight_validate::message::MessageValidationError),
     AttachmentRequest(reqwest::Error),
-    MessageCreate(twilight_http::error::Error),
+    Http(twilight_http::error::Error),
     ResponseDeserialization(twilight_http::response::DeserializeBodyError),
+    RequestValidation(twilight_validate::request::ValidationError),
+    MissingWebhookToken,
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BotError::MessageValidation(err) => write!(f, "invalid message: {err}"),
+            BotError::AttachmentRequest(err) => write!(f, "could not download attachment: {err}"),
+            BotError::Http(err) => err.fmt(f),
+            BotError::ResponseDeserialization(err) => write!(f, "could not read response: {err}"),
+            BotError::RequestValidation(err) => write!(f, "invalid request: {err}"),
+            BotError::MissingWebhookToken => write!(f, "webhook has no token"),
+        }
+    }
 }
 
-impl From<twilight_validate::message::MessageValidationError> for MessageDuplicateError {
+impl From<twilight_validate::message::MessageValidationError> for BotError {
     fn from(value: twilight_validate::message::MessageValidationError) -> Self {
-        MessageDuplicateError::MessageValidation(value)
+        BotError::MessageValidation(value)
     }
 }
 
-impl From<reqwest::Error> for MessageDuplicateError {
+impl From<reqwest::Error> for BotError {
     fn from(value: reqwest::Error) -> Self {
-        MessageDuplicateError::AttachmentRequest(value)
+        BotError::AttachmentRequest(value)
     }
 }
 
-impl From<twilight_http::error::Error> for MessageDuplicateError {
+impl From<twilight_http::error::Error> for BotError {
     fn from(value: twilight_http::error::Error) -> Self {
-        MessageDuplicateError::MessageCreate(value)
+        BotError::Http(value)
     }
 }
 
-impl From<twilight_http::response::DeserializeBodyError> for MessageDuplicateError {
+impl From<twilight_http::response::DeserializeBodyError> for BotError {
     fn from(value: twilight_http::response::DeserializeBodyError) -> Self {
-        MessageDuplicateError::ResponseDeserialization(value)
+        BotError::ResponseDeserialization(value)
+    }
+}
+
+impl From<twilight_validate::request::ValidationError> for BotError {
+    fn from(value: twilight_validate::request::ValidationError) -> Self {
+        BotError::RequestValidation(value)
     }
 }
@@ -3,30 +3,58 @@ use tokio::sync::RwLock;
 use tokio::sync::Mutex;
 use twilight_model::gateway::OpCode;
 use twilight_model::gateway::payload::outgoing::{update_presence::UpdatePresencePayload, UpdatePresence};
+use twilight_model::gateway::presence::{Activity, ActivityType};
 use twilight_gateway::{
-    Intents, Shard, ShardId, 
+    error::ReceiveMessageErrorType, Config, Intents, MessageSender, Session, Shard, ShardId,
 };
 
-use super::{Message, Status, SystemEvent, BotConfig};
+use super::{Message, Status, SystemEvent, BotConfig, GatewayResume, GatewayRole};
+
+// Connection errors in a row before the shard is given up on and the system reconnects it
+const MAX_CONNECTION_ERRORS: u32 = 5;
 
 pub struct Gateway {
     shard: Arc<Mutex<Shard>>,
+    sender: MessageSender,
     bot_conf: Arc<RwLock<BotConfig>>,
 }
 
 impl Gateway {
-    pub fn new(discord_token: &String, bot_conf: &Arc<RwLock<BotConfig>>) -> Self {
-        let intents = Intents::GUILD_MEMBERS
-            | Intents::GUILD_PRESENCES
-            | Intents::GUILD_MESSAGES
-            | Intents::MESSAGE_CONTENT;
+    pub fn new(
+        discord_token: &String,
+        role: GatewayRole,
+        session: Option<Session>,
+        discord_config: &crate::config::DiscordConfig,
+        bot_conf: &Arc<RwLock<BotConfig>>,
+    ) -> Self {
+        // Presence is sent over the connection, it doesn't need to receive anything
+        let intents = match role {
+            GatewayRole::PresenceOnly => Intents::empty(),
+            GatewayRole::Member | GatewayRole::SystemListener => Intents::GUILD_MEMBERS
+                | Intents::GUILD_PRESENCES
+                | Intents::GUILD_MESSAGES
+                | Intents::GUILD_MESSAGE_REACTIONS
+                | Intents::GUILD_MESSAGE_TYPING
+                | Intents::MESSAGE_CONTENT,
+        };
+
+        let mut config = Config::builder(discord_token.clone(), intents);
+        if let Some(gateway_url) = &discord_config.gateway_url {
+            config = config.proxy_url(gateway_url.trim_end_matches('/').to_string());
+        }
+
+        // Picks up where a previous shard left off instead of identifying again
+        if let Some(session) = session {
+            config = config.session(session);
+        }
+
+        let shard = Shard::with_config(ShardId::ONE, config.build());
 
         Self {
-            shard: Arc::new(Mutex::new(Shard::new(
-                ShardId::ONE,
-                discord_token.clone(),
-                intents,
-            ))),
+            // The listener holds the shard lock while waiting on events, so commands go
+            // through the shard's sender instead of waiting for the next event to come in
+            sender: shard.sender(),
+            shard: Arc::new(Mutex::new(shard)),
             bot_conf: bot_conf.clone(),
         }
     }
@@ -35,61 +63,128 @@ impl Gateway {
         {
             let last_status = { (*self.bot_conf.read().await).last_status };
 
-            if status == last_status {
+            if Some(status) == last_status {
                 return
             }
         }
 
-
-        {
-            let mut shard = self.shard.lock().await;
-
-            shard.command(&UpdatePresence {
-                d: UpdatePresencePayload {
-                    activities: Vec::new(),
-                    afk: false,
-                    since: None,
-                    status,
-                },
-                op: OpCode::PresenceUpdate,
-            }).await.expect("Could not send command to gateway");
-        }
-
-        self.bot_conf.write().await.last_status = status;
+        let activities = match &self.bot_conf.read().await.custom_status {
+            None => Vec::new(),
+            Some(custom_status) => vec![Activity {
+                application_id: None,
+                assets: None,
+                buttons: Vec::new(),
+                created_at: None,
+                details: None,
+                emoji: None,
+                flags: None,
+                id: None,
+                instance: None,
+                kind: ActivityType::Custom,
+                name: "Custom Status".to_string(),
+                party: None,
+                secrets: None,
+                state: Some(custom_status.clone()),
+                timestamps: None,
+                url: None,
+            }],
+        };
+
+
+        self.sender.command(&UpdatePresence {
+            d: UpdatePresencePayload {
+                activities,
+                afk: false,
+                since: None,
+                status,
+            },
+            op: OpCode::PresenceUpdate,
+        }).expect("Could not send command to gateway");
+
+        self.bot_conf.write().await.last_status = Some(status);
     }
 
     pub fn start_listening(&self) {
-        let bot_conf = self.bot_conf.clone();
+        let shared_bot_conf = self.bot_conf.clone();
         let shard = self.shard.clone();
         tokio::spawn(async move {
+            let mut connection_errors = 0;
+
             loop {
-                let bot_conf = { (*bot_conf.read().await).clone() };
+                let bot_conf = { (*shared_bot_conf.read().await).clone() };
                 let next_event = { shard.lock().await.next_event().await };
                 let system_channel = bot_conf.system_handler.as_ref().expect("No system channel");
                 let message_channel = bot_conf.message_handler.as_ref().expect("No message channel");
 
+                if next_event.is_ok() {
+                    connection_errors = 0;
+                }
+
                 match next_event {
                     Err(source) => {
                         system_channel
                             .send(SystemEvent::GatewayError(bot_conf.member_id, source.to_string()))
                             .await;
 
-                        if source.is_fatal() {
-                            system_channel.send(SystemEvent::GatewayClosed(bot_conf.member_id)).await;
-                            return;
+                        match source.kind() {
+                            // Bad token, disallowed intents and the like, reconnecting won't help
+                            ReceiveMessageErrorType::FatallyClosed { close_code } => {
+                                let reason = format!("gateway closed with {} ({})", close_code, *close_code as u16);
+                                let _ = system_channel.send(SystemEvent::GatewayFailed(bot_conf.member_id, reason)).await;
+                                return;
+                            }
+
+                            // The shard reconnects by itself, but stop it from retrying forever
+                            ReceiveMessageErrorType::Io | ReceiveMessageErrorType::Reconnect | ReceiveMessageErrorType::SendingMessage => {
+                                connection_errors += 1;
+
+                                if connection_errors >= MAX_CONNECTION_ERRORS {
+                                    let resume = shard.lock().await.session().cloned().zip(bot_conf.user_id)
+                                        .map(|(session, user_id)| GatewayResume { session, user_id });
+
+                                    let _ = system_channel.send(SystemEvent::GatewayClosed(bot_conf.member_id, resume)).await;
+                                    return;
+                                }
+                            }
+
+                            _ => (),
                         }
                     }
                     Ok(event) => match event {
                         twilight_gateway::Event::Ready(ready) => {
+                            shared_bot_conf.write().await.user_id = Some(ready.user.id);
+
                             system_channel
                                 .send(SystemEvent::GatewayConnected(bot_conf.member_id, ready.user.id))
                                 .await;
                         }
 
+                        twilight_gateway::Event::Resumed => {
+                            if let Some(user_id) = bot_conf.user_id {
+                                let _ = system_channel
+                                    .send(SystemEvent::GatewayConnected(bot_conf.member_id, user_id))
+                                    .await;
+                            }
+                        }
+
                         twilight_gateway::Event::MessageCreate(message_create) => {
                             let message = message_create.0;
 
                             if message.author.id != bot_conf.reference_user_id {
+                                // Someone else pinging this member, the system decides whether to forward it.
+                                // A system listener can't tell which users are members, so passes on any mention.
+                                let pings_member = match bot_conf.role {
+                                    GatewayRole::SystemListener => !message.mentions.is_empty(),
+                                    _ => bot_conf.user_id
+                                        .is_some_and(|user_id| message.mentions.iter().any(|mention| mention.id == user_id)),
+                                };
+
+                                if pings_member {
+                                    let _ = system_channel
+                                        .send(SystemEvent::MemberPinged(bot_conf.member_id, message))
+                                        .await;
+                                }
+
                                 continue;
                             }
 
@@ -98,6 +193,26 @@ impl Gateway {
                                 .await;
                         }
 
+                        twilight_gateway::Event::ReactionAdd(reaction_add) => {
+                            if reaction_add.user_id != bot_conf.reference_user_id {
+                                continue;
+                            }
+
+                            let _ = system_channel
+                                .send(SystemEvent::NewReaction(Box::new((reaction_add.0, bot_conf.member_id))))
+                                .await;
+                        }
+
+                        twilight_gateway::Event::TypingStart(typing_start) => {
+                            if typing_start.user_id != bot_conf.reference_user_id {
+                                continue;
+                            }
+
+                            let _ = system_channel
+                                .send(SystemEvent::UserTyping(typing_start.guild_id, typing_start.channel_id))
+                                .await;
+                        }
+
                         twilight_gateway::Event::MessageUpdate(message_update) => {
                             if message_update.author.is_none()
                                 || message_update.author.as_ref().unwrap().id != bot_conf.reference_user_id
@@ -110,7 +225,7 @@ impl Gateway {
                             }
 
                             message_channel
-                                .send((message_update.edited_timestamp.unwrap(), Message::Partial(*message_update, bot_conf.member_id)))
+                                .send((message_update.edited_timestamp.unwrap(), Message::Partial(message_update, bot_conf.member_id)))
                                 .await;
                         }
 
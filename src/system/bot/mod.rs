@@ -4,49 +4,71 @@ mod gateway;
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::RwLock;
-use twilight_http::error::Error as TwiError;
-use twilight_http::request::channel::reaction::RequestReactionType;
 
 pub use super::types::*;
-pub use client::MessageDuplicateError;
+use super::backend::ProxyBackend;
+pub use client::BotError;
 use gateway::Gateway;
 use client::Client;
 
+/// What a bot's gateway connection is for
+#[derive(Clone, Copy, PartialEq)]
+pub enum GatewayRole {
+    /// Receives events for its own member, every member's bot sees the same messages
+    Member,
+    /// Receives events on behalf of the whole system, including pings of any member
+    SystemListener,
+    /// Connects only to show presence, leaving events to the system's listener
+    PresenceOnly,
+}
+
 #[derive(Clone)]
 pub struct BotConfig {
     pub member_id: MemberId,
+    pub role: GatewayRole,
     pub reference_user_id: UserId,
-    pub discord_token: String,
-    pub last_status: Status,
+    pub user_id: Option<UserId>,
+    pub custom_status: Option<String>,
+    pub last_status: Option<Status>,
     pub message_handler: Option<Sender<MessageEvent>>,
     pub system_handler: Option<Sender<SystemEvent>>,
 }
 
 pub struct Bot {
     bot_conf: Arc<RwLock<BotConfig>>,
-    gateway: Gateway,
+    gateway: RwLock<Gateway>,
     client: Client,
+    discord_token: String,
+    discord_config: crate::config::DiscordConfig,
 }
 
 impl Bot {
     pub fn new(
         member_id: MemberId,
-        config: &crate::config::Member,
+        role: GatewayRole,
+        resume: Option<GatewayResume>,
+        discord_token: &String,
+        custom_status: Option<String>,
         reference_user_id: UserId,
+        discord_config: &crate::config::DiscordConfig,
     ) -> Self {
         let bot_conf = Arc::new(RwLock::new(BotConfig {
             member_id,
+            role,
             reference_user_id,
-            discord_token: config.discord_token.clone(),
-            last_status: Status::Online,
+            user_id: resume.as_ref().map(|resume| resume.user_id),
+            custom_status,
+            last_status: None,
             message_handler: None,
             system_handler: None,
         }));
 
         Self {
-            gateway: Gateway::new(&config.discord_token, &bot_conf),
-            client: Client::new(&config.discord_token, &bot_conf),
+            gateway: RwLock::new(Gateway::new(discord_token, role, resume.map(|resume| resume.session), discord_config, &bot_conf)),
+            client: Client::new(discord_token, discord_config, &bot_conf),
             bot_conf,
+            discord_token: discord_token.clone(),
+            discord_config: discord_config.clone(),
         }
     }
 
@@ -58,44 +80,89 @@ impl Bot {
         self.bot_conf.write().await.system_handler = Some(handler);
     }
 
-    pub async fn set_status(&self, status: Status) {
-        self.gateway.set_status(status).await;
+    pub async fn start(&self) {
+        self.gateway.read().await.start_listening()
     }
 
-    pub async fn set_nick(&self, server_id: ServerId, nick: String) {
-        self.client.set_nick(server_id, nick.as_str()).await.expect("Could not update nick")
+    /// Replaces a closed gateway connection, keeping the HTTP client and what it has cached
+    pub async fn reconnect(&self, resume: Option<GatewayResume>) {
+        let role = {
+            let mut bot_conf = self.bot_conf.write().await;
+            bot_conf.last_status = None;
+            bot_conf.role
+        };
+
+        let gateway = Gateway::new(&self.discord_token, role, resume.map(|resume| resume.session), &self.discord_config, &self.bot_conf);
+        gateway.start_listening();
+
+        *self.gateway.write().await = gateway;
     }
 
-    pub fn start(&self) {
-        self.gateway.start_listening()
+    pub async fn execute_webhook(&self, message: &TwiMessage, content: &str, username: &str, avatar_url: Option<&str>) -> Result<TwiMessage, BotError> {
+        self.client.execute_webhook(message, content, username, avatar_url).await
     }
 
-    pub async fn fetch_message(&self, message_id: MessageId, channel_id: ChannelId) -> TwiMessage {
-        self.client.fetch_message(message_id, channel_id).await
+    pub async fn fetch_webhook_message(&self, channel_id: ChannelId, message_id: MessageId) -> Result<FullMessage, BotError> {
+        self.client.fetch_webhook_message(channel_id, message_id).await
     }
 
-    pub async fn fetch_recent_channel_messages(&self, channel_id: ChannelId) -> Result<Vec<FullMessage>, TwiError> {
-        self.client.fetch_recent_channel_messages(channel_id).await
+    pub async fn edit_webhook_message(&self, channel_id: ChannelId, message_id: MessageId, new_content: String) -> Result<FullMessage, BotError> {
+        self.client.edit_webhook_message(channel_id, message_id, new_content).await
     }
+}
+
+impl ProxyBackend for Bot {
+    type Error = BotError;
 
-    pub async fn resend_message(&self, message_id: MessageId, channel_id: ChannelId) {
+    async fn set_status(&self, status: Status) {
+        self.gateway.read().await.set_status(status).await;
+    }
+
+    async fn set_nick(&self, server_id: ServerId, nick: String) {
+        self.client.set_nick(server_id, nick.as_str()).await.expect("Could not update nick")
+    }
+
+    async fn resend_message(&self, message_id: MessageId, channel_id: ChannelId) {
         self.client.resend_message(message_id, channel_id).await;
     }
 
-    pub async fn edit_message(&self, channel_id: ChannelId, message_id: MessageId, new_content: String) -> Result<FullMessage, TwiError> {
+    async fn fetch_recent_channel_messages(&self, channel_id: ChannelId) -> Result<Vec<FullMessage>, BotError> {
+        Ok(self.client.fetch_recent_channel_messages(channel_id).await?)
+    }
+
+    async fn trigger_typing(&self, channel_id: ChannelId) -> Result<(), BotError> {
+        Ok(self.client.trigger_typing(channel_id).await?)
+    }
+
+    async fn send_message(&self, channel_id: ChannelId, content: &str) -> Result<FullMessage, BotError> {
+        self.client.send_message(channel_id, content).await
+    }
+
+    async fn send_direct_message(&self, user_id: UserId, content: &str) -> Result<FullMessage, BotError> {
+        self.client.send_direct_message(user_id, content).await
+    }
+
+    async fn duplicate_message(&self, message: &TwiMessage, content: &str) -> Result<TwiMessage, BotError> {
+        self.client.duplicate_message(message, content).await
+    }
+
+    async fn edit_message(&self, channel_id: ChannelId, message_id: MessageId, new_content: String) -> Result<FullMessage, BotError> {
         self.client.edit_message(channel_id, message_id, new_content).await
     }
 
-    pub async fn delete_message(&self, channel_id: ChannelId, message_id: MessageId) -> Result<(), TwiError> {
-        self.client.delete_message(channel_id, message_id).await
+    async fn delete_message(&self, channel_id: ChannelId, message_id: MessageId) -> Result<(), BotError> {
+        Ok(self.client.delete_message(channel_id, message_id).await?)
     }
 
-    pub async fn react_message(&self, channel_id: ChannelId, message_id: MessageId, react: &'_ RequestReactionType<'_>) -> Result<(), TwiError> {
-        self.client.react_message(channel_id, message_id, react).await
+    async fn react_message(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji) -> Result<(), BotError> {
+        Ok(self.client.react_message(channel_id, message_id, &emoji.as_request()).await?)
     }
 
-    pub async fn duplicate_message(&self, message_id: &TwiMessage, content: &str) ->  Result<TwiMessage, MessageDuplicateError> {
-        self.client.duplicate_message(message_id, content).await
+    async fn unreact_message(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji) -> Result<(), BotError> {
+        Ok(self.client.unreact_message(channel_id, message_id, &emoji.as_request()).await?)
     }
-}
 
+    async fn remove_reaction(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji, user_id: UserId) -> Result<(), BotError> {
+        Ok(self.client.remove_reaction(channel_id, message_id, &emoji.as_request(), user_id).await?)
+    }
+}
@@ -0,0 +1,596 @@
+//! An in-process stand-in for Discord's REST API and gateway, enough to drive a `Manager`
+//! through proxying, edits, deletes and reproxies in tests.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::time::sleep;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message as WebsocketMessage;
+use twilight_model::id::Id;
+use twilight_model::util::Timestamp;
+
+use super::{ChannelId, MessageId, ServerId, TwiMessage, UserId};
+
+pub const REFERENCE_USER: u64 = 100;
+/// Someone outside the system
+pub const OTHER_USER: u64 = 300;
+pub const SERVER: u64 = 400;
+pub const CHANNEL: u64 = 500;
+/// A public thread in CHANNEL
+pub const THREAD: u64 = 510;
+
+pub struct FakeDiscord {
+    pub api_url: String,
+    pub gateway_url: String,
+    state: Arc<Mutex<FakeState>>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    next_id: u64,
+    bot_users: HashMap<String, UserId>,
+    messages: Vec<TwiMessage>,
+    deleted: HashSet<MessageId>,
+    reactions: Vec<(MessageId, String, UserId)>,
+    webhooks: Vec<Value>,
+    rejected_tokens: HashSet<String>,
+    broken_direct_messages: bool,
+    identified_tokens: Vec<String>,
+    // Every HTTP request made, as the method and path without its query
+    requests: Vec<String>,
+    // Each connection's intents alongside where to send its events
+    shards: Vec<(u64, UnboundedSender<(&'static str, Value)>)>,
+}
+
+impl FakeDiscord {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(FakeState { next_id: 1000, ..Default::default() }));
+
+        let http_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let gateway_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", http_listener.local_addr().unwrap());
+        let gateway_url = format!("ws://{}", gateway_listener.local_addr().unwrap());
+
+        let http_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = http_listener.accept().await {
+                tokio::spawn(serve_http(stream, http_state.clone()));
+            }
+        });
+
+        let gateway_state = state.clone();
+        let resume_url = gateway_url.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = gateway_listener.accept().await {
+                tokio::spawn(serve_gateway(stream, gateway_state.clone(), resume_url.clone()));
+            }
+        });
+
+        Self { api_url, gateway_url, state }
+    }
+
+    /// Closes the gateway with an authentication failure whenever this token identifies
+    pub fn reject_token(&self, token: &str) {
+        self.state.lock().unwrap().rejected_tokens.insert(token.to_string());
+    }
+
+    /// Answers requests for a DM channel with something that isn't a channel
+    pub fn break_direct_messages(&self) {
+        self.state.lock().unwrap().broken_direct_messages = true;
+    }
+
+    pub fn identify_count(&self, token: &str) -> usize {
+        self.state.lock().unwrap().identified_tokens.iter().filter(|identified| *identified == token).count()
+    }
+
+    /// How many times a request like "GET channels/500/webhooks" was made
+    pub fn request_count(&self, request: &str) -> usize {
+        self.state.lock().unwrap().requests.iter().filter(|made| *made == request).count()
+    }
+
+    pub fn bot_user(&self, token: &str) -> UserId {
+        self.state.lock().unwrap().bot_user(token)
+    }
+
+    /// Sends a message as the reference user, dispatching it to every connected bot
+    pub fn user_sends(&self, content: &str) -> MessageId {
+        self.user_sends_in(Id::new(CHANNEL), content)
+    }
+
+    /// Sends a message as the reference user in a specific channel or thread
+    pub fn user_sends_in(&self, channel_id: ChannelId, content: &str) -> MessageId {
+        self.user_sends_message(channel_id, content, None)
+    }
+
+    /// Sends a message as the reference user, replying to another
+    pub fn user_replies(&self, reply_to: MessageId, content: &str) -> MessageId {
+        self.user_sends_message(Id::new(CHANNEL), content, Some(reply_to))
+    }
+
+    fn user_sends_message(&self, channel_id: ChannelId, content: &str, reply_to: Option<MessageId>) -> MessageId {
+        let mut state = self.state.lock().unwrap();
+        let author = json!({ "id": REFERENCE_USER.to_string(), "username": "user", "discriminator": "0001", "avatar": null });
+        let message = state.create_message(channel_id, author, content, reply_to);
+
+        state.dispatch("MESSAGE_CREATE", serde_json::to_value(&message).unwrap());
+        message.id
+    }
+
+    /// Sends a message from someone outside the system, mentioning the bot with this token
+    pub fn other_user_mentions(&self, token: &str, content: &str) -> MessageId {
+        let mut state = self.state.lock().unwrap();
+        let mentioned = state.bot_user(token);
+        let author = json!({ "id": OTHER_USER.to_string(), "username": "someone", "discriminator": "0001", "avatar": null });
+
+        let mut message = state.create_message(Id::new(CHANNEL), author, content, None);
+        let mut message_json = serde_json::to_value(&message).unwrap();
+        message_json["mentions"] = json!([{ "id": mentioned, "username": "bot", "discriminator": "0001", "avatar": null, "bot": true, "public_flags": 0 }]);
+        message = serde_json::from_value(message_json.clone()).unwrap();
+
+        *state.messages.last_mut().unwrap() = message.clone();
+        state.dispatch("MESSAGE_CREATE", message_json);
+        message.id
+    }
+
+    /// Edits a message as the reference user, even one that was already deleted
+    pub fn user_edits(&self, message_id: MessageId, content: &str) {
+        let mut state = self.state.lock().unwrap();
+        let mut message = state.message(message_id).cloned().expect("No such message");
+        message.content = content.to_string();
+        message.edited_timestamp = Some(now());
+
+        state.dispatch("MESSAGE_UPDATE", serde_json::to_value(&message).unwrap());
+    }
+
+    /// Deletes a message the way a moderator would, without the system's involvement
+    pub fn delete(&self, message_id: MessageId) {
+        self.state.lock().unwrap().deleted.insert(message_id);
+    }
+
+    pub fn message(&self, message_id: MessageId) -> Option<TwiMessage> {
+        self.state.lock().unwrap().message(message_id).cloned()
+    }
+
+    pub fn is_deleted(&self, message_id: MessageId) -> bool {
+        self.state.lock().unwrap().deleted.contains(&message_id)
+    }
+
+    pub fn reactions(&self, message_id: MessageId) -> Vec<String> {
+        self.state.lock().unwrap().reactions.iter()
+            .filter(|(reacted_id, _, _)| *reacted_id == message_id)
+            .map(|(_, emoji, _)| emoji.clone())
+            .collect()
+    }
+
+    pub async fn wait_for_shards(&self, count: usize) {
+        self.wait_for(&format!("{count} gateway connections"), || {
+            (self.state.lock().unwrap().shards.len() >= count).then_some(())
+        }).await
+    }
+
+    /// Connections that asked for any events at all, rather than just showing presence
+    pub fn listening_shards(&self) -> usize {
+        self.state.lock().unwrap().shards.iter().filter(|(intents, _)| *intents != 0).count()
+    }
+
+    /// Waits for a message that is still around and matches the predicate
+    pub async fn wait_for_message(&self, description: &str, predicate: impl Fn(&TwiMessage) -> bool) -> TwiMessage {
+        self.wait_for(description, || {
+            let state = self.state.lock().unwrap();
+            state.messages.iter()
+                .filter(|message| !state.deleted.contains(&message.id))
+                .find(|message| predicate(message))
+                .cloned()
+        }).await
+    }
+
+    pub async fn wait_for_deletion(&self, message_id: MessageId) {
+        self.wait_for(&format!("deletion of message {message_id}"), || {
+            self.is_deleted(message_id).then_some(())
+        }).await
+    }
+
+    pub async fn wait_for<T>(&self, description: &str, check: impl Fn() -> Option<T>) -> T {
+        for _ in 0..500 {
+            if let Some(result) = check() {
+                return result
+            }
+
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        panic!("Timed out waiting for {description}")
+    }
+}
+
+impl FakeState {
+    fn next_id<T>(&mut self) -> Id<T> {
+        self.next_id += 1;
+        Id::new(self.next_id)
+    }
+
+    fn bot_user(&mut self, token: &str) -> UserId {
+        let token = token.trim_start_matches("Bot ").to_string();
+
+        if let Some(user_id) = self.bot_users.get(&token) {
+            return *user_id
+        }
+
+        let user_id = self.next_id();
+        self.bot_users.insert(token, user_id);
+        user_id
+    }
+
+    fn webhook(&self, webhook_id: &str, webhook_token: &str) -> Option<Value> {
+        self.webhooks.iter()
+            .find(|webhook| webhook["id"] == webhook_id && webhook["token"] == webhook_token)
+            .cloned()
+    }
+
+    fn message(&self, message_id: MessageId) -> Option<&TwiMessage> {
+        self.messages.iter().find(|message| message.id == message_id)
+    }
+
+    fn message_mut(&mut self, message_id: MessageId) -> Option<&mut TwiMessage> {
+        if self.deleted.contains(&message_id) {
+            return None
+        }
+
+        self.messages.iter_mut().find(|message| message.id == message_id)
+    }
+
+    fn create_message(&mut self, channel_id: ChannelId, author: Value, content: &str, reply_to: Option<MessageId>) -> TwiMessage {
+        let id: MessageId = self.next_id();
+        let server_id: ServerId = Id::new(SERVER);
+        let referenced_message = reply_to.and_then(|reply_to| self.message(reply_to)).cloned();
+
+        let message: TwiMessage = serde_json::from_value(json!({
+            "id": id,
+            "channel_id": channel_id,
+            "guild_id": server_id,
+            "author": author,
+            "content": content,
+            "timestamp": now().iso_8601().to_string(),
+            "edited_timestamp": null,
+            "tts": false,
+            "mention_everyone": false,
+            "mentions": [],
+            "mention_roles": [],
+            "attachments": [],
+            "embeds": [],
+            "pinned": false,
+            "type": if referenced_message.is_some() { 19 } else { 0 },
+            "message_reference": reply_to.map(|reply_to| json!({ "message_id": reply_to, "channel_id": channel_id })),
+            "referenced_message": referenced_message,
+        })).unwrap();
+
+        self.messages.push(message.clone());
+        message
+    }
+
+    fn dispatch(&mut self, event: &'static str, data: Value) {
+        // Connections that went away just drop out, ones without intents get nothing
+        self.shards.retain(|(intents, shard)| *intents == 0 || shard.send((event, data.clone())).is_ok());
+    }
+
+    fn handle_request(&mut self, method: &str, path: &str, token: &str, body: &[u8]) -> (u16, Value) {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let path = path.trim_start_matches("/api/v10/");
+        let segments: Vec<&str> = path.split('/').collect();
+        self.requests.push(format!("{method} {path}"));
+        let body: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+
+        match (method, segments.as_slice()) {
+            ("GET", ["channels", channel_id]) => {
+                let channel = if *channel_id == THREAD.to_string() {
+                    json!({ "id": channel_id, "type": 11, "guild_id": SERVER.to_string(), "parent_id": CHANNEL.to_string() })
+                } else {
+                    json!({ "id": channel_id, "type": 0, "guild_id": SERVER.to_string() })
+                };
+
+                (200, channel)
+            },
+
+            ("GET", ["channels", _, "messages"]) => {
+                let channel_id: ChannelId = segment_id(&segments, 1);
+                let messages: Vec<&TwiMessage> = self.messages.iter().rev()
+                    .filter(|message| message.channel_id == channel_id && !self.deleted.contains(&message.id))
+                    .take(10)
+                    .collect();
+
+                (200, json!(messages))
+            },
+
+            ("POST", ["channels", _, "messages"]) => {
+                let user_id = self.bot_user(token);
+                let author = json!({ "id": user_id, "username": "bot", "discriminator": "0001", "avatar": null, "bot": true });
+                let reply_to = body["message_reference"]["message_id"].as_str()
+                    .and_then(|reply_to| reply_to.parse::<u64>().ok())
+                    .map(Id::new);
+
+                let message = self.create_message(segment_id(&segments, 1), author, body["content"].as_str().unwrap_or(""), reply_to);
+                let message = serde_json::to_value(&message).unwrap();
+
+                self.dispatch("MESSAGE_CREATE", message.clone());
+                (200, message)
+            },
+
+            ("GET", ["channels", _, "messages", _]) => match self.message(segment_id(&segments, 3)) {
+                Some(message) => (200, json!(message)),
+                None => not_found(),
+            },
+
+            ("PATCH", ["channels", _, "messages", _]) => match self.message_mut(segment_id(&segments, 3)) {
+                Some(message) => {
+                    if let Some(content) = body["content"].as_str() {
+                        message.content = content.to_string();
+                    }
+                    message.edited_timestamp = Some(now());
+
+                    (200, json!(message))
+                },
+                None => not_found(),
+            },
+
+            ("DELETE", ["channels", _, "messages", _]) => {
+                let message_id = segment_id(&segments, 3);
+
+                if self.message(message_id).is_none() || !self.deleted.insert(message_id) {
+                    return not_found()
+                }
+
+                (204, Value::Null)
+            },
+
+            ("PUT", ["channels", _, "messages", _, "reactions", emoji, "@me"]) => {
+                let user_id = self.bot_user(token);
+                self.reactions.push((segment_id(&segments, 3), emoji.to_string(), user_id));
+                (204, Value::Null)
+            },
+
+            ("DELETE", ["channels", _, "messages", _, "reactions", emoji, user]) => {
+                let message_id = segment_id(&segments, 3);
+                let user_id = if *user == "@me" { self.bot_user(token) } else { segment_id(&segments, 6) };
+
+                self.reactions.retain(|reaction| *reaction != (message_id, emoji.to_string(), user_id));
+                (204, Value::Null)
+            },
+
+            ("POST", ["channels", _, "typing"]) => (204, Value::Null),
+
+            ("POST", ["users", "@me", "channels"]) => {
+                if self.broken_direct_messages {
+                    return (200, json!({ "unexpected": true }))
+                }
+
+                let channel_id: ChannelId = self.next_id();
+                (200, json!({ "id": channel_id, "type": 1, "recipients": [{ "id": body["recipient_id"], "username": "user", "discriminator": "0001", "avatar": null }] }))
+            },
+
+            ("GET", ["channels", _, "webhooks"]) => {
+                let channel_id = segments[1];
+                let webhooks: Vec<&Value> = self.webhooks.iter().filter(|webhook| webhook["channel_id"] == channel_id).collect();
+                (200, json!(webhooks))
+            },
+
+            ("POST", ["channels", channel_id, "webhooks"]) if *channel_id == THREAD.to_string() => {
+                (400, json!({ "code": 50024, "message": "Cannot execute action on this channel type" }))
+            },
+
+            ("POST", ["channels", _, "webhooks"]) => {
+                let webhook_id: u64 = self.next_id::<()>().get();
+                let user_id = self.bot_user(token);
+                let webhook = json!({
+                    "id": webhook_id.to_string(),
+                    "type": 1,
+                    "channel_id": segments[1],
+                    "guild_id": SERVER.to_string(),
+                    "name": body["name"],
+                    "token": format!("webhook-token-{webhook_id}"),
+                    "user": { "id": user_id, "username": "bot", "discriminator": "0001", "avatar": null, "bot": true },
+                });
+
+                self.webhooks.push(webhook.clone());
+                (200, webhook)
+            },
+
+            ("POST", ["webhooks", webhook_id, webhook_token]) => {
+                let Some(webhook) = self.webhook(webhook_id, webhook_token) else {
+                    return not_found()
+                };
+
+                let thread_id = query.split('&').find_map(|parameter| parameter.strip_prefix("thread_id="));
+                let channel_id: ChannelId = thread_id.unwrap_or(webhook["channel_id"].as_str().unwrap()).parse().unwrap();
+                let author = json!({ "id": webhook_id, "username": body["username"], "discriminator": "0000", "avatar": null, "bot": true });
+
+                let mut message = self.create_message(channel_id, author, body["content"].as_str().unwrap_or(""), None);
+                message.webhook_id = Some(segment_id(&segments, 1));
+                self.messages.last_mut().unwrap().webhook_id = message.webhook_id;
+
+                let message = serde_json::to_value(&message).unwrap();
+                self.dispatch("MESSAGE_CREATE", message.clone());
+                (200, message)
+            },
+
+            ("GET", ["webhooks", webhook_id, webhook_token, "messages", _]) => {
+                if self.webhook(webhook_id, webhook_token).is_none() {
+                    return not_found()
+                }
+
+                match self.message(segment_id(&segments, 4)) {
+                    Some(message) if message.webhook_id == Some(segment_id(&segments, 1)) && !self.deleted.contains(&message.id) => (200, json!(message)),
+                    _ => not_found(),
+                }
+            },
+
+            ("PATCH", ["webhooks", webhook_id, webhook_token, "messages", _]) => {
+                if self.webhook(webhook_id, webhook_token).is_none() {
+                    return not_found()
+                }
+
+                match self.message_mut(segment_id(&segments, 4)) {
+                    Some(message) if message.webhook_id == Some(segment_id(&segments, 1)) => {
+                        if let Some(content) = body["content"].as_str() {
+                            message.content = content.to_string();
+                        }
+                        message.edited_timestamp = Some(now());
+
+                        (200, json!(message))
+                    },
+                    _ => not_found(),
+                }
+            },
+
+            _ => not_found(),
+        }
+    }
+}
+
+fn segment_id<T>(segments: &[&str], index: usize) -> Id<T> {
+    Id::new(segments[index].parse().unwrap_or(1))
+}
+
+fn not_found() -> (u16, Value) {
+    (404, json!({ "code": 10008, "message": "Unknown Message" }))
+}
+
+fn now() -> Timestamp {
+    Timestamp::from_micros(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as i64).unwrap()
+}
+
+async fn serve_http(stream: TcpStream, state: Arc<Mutex<FakeState>>) -> std::io::Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    // Keep answering requests until the client hangs up
+    loop {
+        let mut request_line = String::new();
+        if stream.read_line(&mut request_line).await? == 0 {
+            return Ok(())
+        }
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut header = String::new();
+            stream.read_line(&mut header).await?;
+
+            match header.trim_end().split_once(':') {
+                Some((name, value)) => { headers.insert(name.to_lowercase(), value.trim().to_string()); },
+                None => break,
+            }
+        }
+
+        let content_length = headers.get("content-length").and_then(|length| length.parse().ok()).unwrap_or(0);
+        let mut body = vec![0u8; content_length];
+        stream.read_exact(&mut body).await?;
+
+        let mut request = request_line.split_whitespace();
+        let (method, path) = (request.next().unwrap_or(""), request.next().unwrap_or(""));
+        let token = headers.get("authorization").map_or("", |token| token.as_str());
+
+        let (status, response_body) = state.lock().unwrap().handle_request(method, path, token, &body);
+
+        let response_body = if status == 204 { String::new() } else { response_body.to_string() };
+        let reason = match status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            _ => "Not Found",
+        };
+
+        let response = format!(
+            "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            response_body.len(),
+            response_body,
+        );
+        stream.get_mut().write_all(response.as_bytes()).await?;
+    }
+}
+
+async fn serve_gateway(stream: TcpStream, state: Arc<Mutex<FakeState>>, resume_url: String) {
+    let Ok(mut websocket) = tokio_tungstenite::accept_async(stream).await else {
+        return
+    };
+
+    let hello = json!({ "op": 10, "d": { "heartbeat_interval": 41250 } });
+    if websocket.send(WebsocketMessage::Text(hello.to_string())).await.is_err() {
+        return
+    }
+
+    let (dispatch_sender, mut dispatches) = unbounded_channel();
+    let mut sequence = 0;
+
+    loop {
+        let outgoing = tokio::select! {
+            incoming = websocket.next() => {
+                let Some(Ok(WebsocketMessage::Text(text))) = incoming else {
+                    match incoming {
+                        Some(Ok(_)) => continue,
+                        _ => return,
+                    }
+                };
+
+                let payload: Value = serde_json::from_str(&text).unwrap_or(Value::Null);
+
+                match payload["op"].as_u64() {
+                    // Heartbeat
+                    Some(1) => json!({ "op": 11 }),
+
+                    // Identify
+                    Some(2) => {
+                        let token = payload["d"]["token"].as_str().unwrap_or("").trim_start_matches("Bot ").to_string();
+                        let rejected = {
+                            let mut state = state.lock().unwrap();
+                            state.identified_tokens.push(token.clone());
+                            state.rejected_tokens.contains(&token)
+                        };
+
+                        if rejected {
+                            let _ = websocket.send(WebsocketMessage::Close(Some(CloseFrame {
+                                code: CloseCode::from(4004),
+                                reason: "Authentication failed.".into(),
+                            }))).await;
+                            return
+                        }
+
+                        let user_id = {
+                            let mut state = state.lock().unwrap();
+                            state.shards.push((payload["d"]["intents"].as_u64().unwrap_or(0), dispatch_sender.clone()));
+                            state.bot_user(&token)
+                        };
+
+                        sequence += 1;
+                        json!({ "op": 0, "t": "READY", "s": sequence, "d": {
+                            "v": 10,
+                            "user": { "id": user_id, "username": "bot", "discriminator": "0001", "avatar": null, "bot": true, "mfa_enabled": false },
+                            "guilds": [],
+                            "session_id": format!("session-{user_id}"),
+                            "resume_gateway_url": resume_url,
+                            "shard": [0, 1],
+                            "application": { "id": user_id, "flags": 0 },
+                        }})
+                    },
+
+                    // Presence updates and the like don't need an answer
+                    _ => continue,
+                }
+            },
+
+            Some((event, data)) = dispatches.recv() => {
+                sequence += 1;
+                json!({ "op": 0, "t": event, "s": sequence, "d": data })
+            },
+        };
+
+        if websocket.send(WebsocketMessage::Text(outgoing.to_string())).await.is_err() {
+            return
+        }
+    }
+}
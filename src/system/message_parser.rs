@@ -1,11 +1,12 @@
 use std::sync::LazyLock;
 use regex::{Regex, RegexBuilder};
 
-use crate::config::System;
+use crate::config::{is_content_group, AutoProxyScope, AutoproxyConfig, System};
 
 use twilight_mention::ParseMention;
+use twilight_model::gateway::GatewayReaction;
 use twilight_model::id::{marker::UserMarker, Id};
-use super::{FullMessage, MemberId, MessageId, Timestamp, UserId};
+use super::{ChannelId, Emoji, FullMessage, LatchScope, LatchState, MemberId, MessageId, ServerId, Timestamp, UserId};
 
 pub enum ParsedMessage {
     Command(Command),
@@ -18,9 +19,11 @@ pub enum ParsedMessage {
     UnproxiedMessage(Option<String>),
     LatchClear(MemberId),
 
-    // TODO: Figure out how to represent emotes
-    EmoteAdd(MemberId, MessageId, ()),
-    EmoteRemove(MemberId, MessageId, ()),
+    // Message will be proxied by PluralKit, optionally switching to one of our members
+    LeaveForPluralkit(Option<MemberId>),
+
+    EmoteAdd(MemberId, MessageId, Emoji),
+    EmoteRemove(MemberId, MessageId, Emoji),
 }
 
 pub enum Command {
@@ -29,6 +32,9 @@ pub enum Command {
     Delete(MessageId),
     Nick(MemberId, String),
     Log(String),
+    Switch(Vec<MemberId>),
+    Front,
+    FrontHistory,
     ReloadSystemConfig,
     ExitSéance,
     UnknownCommand,
@@ -38,11 +44,25 @@ pub enum Command {
 pub struct MessageParser {}
 
 static CORRECTION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
-   Regex::new(r"^\*\B+$").unwrap()
+   Regex::new(r"^\*[^*\s]+$").unwrap()
+});
+
+static REACTION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+   // Unicode emoji are a pictograph or flag letter, then any modifiers, joiners and further pictographs
+   Regex::new(concat!(
+       r"^(?<action>[+-])(?:<a?:(?<name>\w+):(?<id>\d+)>|",
+       r"(?<unicode>[\p{Extended_Pictographic}\p{Regional_Indicator}]",
+       r"[\p{Extended_Pictographic}\p{Regional_Indicator}\p{Emoji_Modifier}\x{200D}\x{FE0F}\x{20E3}\x{E0020}-\x{E007F}]*))$",
+   )).unwrap()
 });
 
 impl MessageParser {
-    pub fn parse(message: &FullMessage, secondary_message: Option<&FullMessage>, system_config: &System, latch_state: Option<(MemberId, Timestamp)>) -> ParsedMessage {
+    /// `secondary_member` is the member who sent `secondary_message`, if it was one of ours
+    pub fn parse(message: &FullMessage, secondary_message: Option<&FullMessage>, secondary_member: Option<MemberId>, system_config: &System, latch_state: &LatchState) -> ParsedMessage {
+        let latch_state = latch_state
+            .get(&system_config.latch_scope(message.guild_id, message.channel_id))
+            .copied();
+
         if message.content == r"\\" {
             return ParsedMessage::LatchClear(if let Some((member_id, _)) = latch_state {
                 member_id
@@ -56,15 +76,23 @@ impl MessageParser {
         }
 
         if message.content.starts_with(r"!") {
-            if let Some(parse) = MessageParser::check_command(message, secondary_message, system_config, latch_state) {
+            if let Some(parse) = MessageParser::check_command(message, secondary_message, secondary_member, system_config, latch_state) {
                 return ParsedMessage::Command(parse);
             } else {
                 return ParsedMessage::UnproxiedMessage(Some(format!("Unknown command string: {}", message.content)));
             }
         }
 
+        if let Some(parse) = MessageParser::check_reaction(message, secondary_message, secondary_member, latch_state) {
+            return parse
+        }
+
+        if let Some(parse) = MessageParser::check_pluralkit(message, system_config) {
+            return parse
+        }
+
         if CORRECTION_REGEX.is_match(message.content.as_str()) {
-            if let Some(parse) = MessageParser::check_correction(message, secondary_message) {
+            if let Some(parse) = MessageParser::check_correction(message, secondary_message, secondary_member) {
                 return parse
             }
         }
@@ -81,7 +109,7 @@ impl MessageParser {
         ParsedMessage::UnproxiedMessage(None)
     }
 
-    fn check_command(message: &FullMessage, secondary_message: Option<&FullMessage>, system_config: &System, latch_state: Option<(MemberId, Timestamp)>) -> Option<Command> {
+    fn check_command(message: &FullMessage, secondary_message: Option<&FullMessage>, secondary_member: Option<MemberId>, system_config: &System, latch_state: Option<(MemberId, Timestamp)>) -> Option<Command> {
         let mut words = message.content.strip_prefix("!").unwrap().split_whitespace();
         let first_word = words.next();
 
@@ -93,7 +121,9 @@ impl MessageParser {
                     return Some(Command::Log(remainder));
                 },
                 "edit" => {
-                    let editing_member = Self::get_member_id_from_user_id(secondary_message.as_ref().unwrap().author.id, system_config).unwrap();
+                    let Some(editing_member) = secondary_member else {
+                        return Some(Command::InvalidCommand)
+                    };
                     return Some(Command::Edit(editing_member, secondary_message.unwrap().id, words.remainder().unwrap().to_string()));
                 },
                 "nick" => {
@@ -108,7 +138,26 @@ impl MessageParser {
                 },
                 "delete" => {
                     return Some(Command::Delete(secondary_message.unwrap().id));
-                }
+                },
+                "reload" => {
+                    return Some(Command::ReloadSystemConfig);
+                },
+                "exit" => {
+                    return Some(Command::ExitSéance);
+                },
+                "switch" => {
+                    let members: Option<Vec<MemberId>> = words
+                        .map(|word| MessageParser::match_member(Some(word), system_config))
+                        .collect();
+
+                    return Some(members.map_or(Command::InvalidCommand, Command::Switch));
+                },
+                "front" => {
+                    return Some(Command::Front);
+                },
+                "fronthistory" => {
+                    return Some(Command::FrontHistory);
+                },
                 _ => (),
             },
         }
@@ -156,7 +205,9 @@ impl MessageParser {
                 original_content.replace(pattern, replacement)
             };
 
-            let editing_member = Self::get_member_id_from_user_id(secondary_message.as_ref().unwrap().author.id, system_config).unwrap();
+            let Some(editing_member) = secondary_member else {
+                return Some(Command::InvalidCommand)
+            };
             return Some(Command::Edit(editing_member, secondary_message.as_ref().unwrap().id, new_content.to_string()));
         }
 
@@ -164,8 +215,92 @@ impl MessageParser {
         None
     }
 
-    fn check_correction(message: &FullMessage, secondary_message: Option<&FullMessage>) -> Option<ParsedMessage> {
-        None
+    fn check_correction(message: &FullMessage, secondary_message: Option<&FullMessage>, secondary_member: Option<MemberId>) -> Option<ParsedMessage> {
+        let secondary_message = secondary_message?;
+        let editing_member = secondary_member?;
+        let correction = message.content.strip_prefix("*")?;
+        let original_content = secondary_message.content.as_str();
+
+        // Find the word closest to the correction, ignoring punctuation around it
+        let (start, end, distance) = original_content
+            .split_whitespace()
+            .filter_map(|word| {
+                let core = word.trim_matches(|c: char| !c.is_alphanumeric());
+                if core.is_empty() {
+                    return None
+                }
+
+                let start = core.as_ptr() as usize - original_content.as_ptr() as usize;
+                let distance = edit_distance(&core.to_lowercase(), &correction.to_lowercase());
+                Some((start, start + core.len(), distance))
+            })
+            .min_by_key(|(_, _, distance)| *distance)?;
+
+        // Too different to be a typo of this word. An exact match still counts, and leaves the message as it was.
+        let word_length = std::cmp::max(original_content[start..end].chars().count(), correction.chars().count());
+        if distance > word_length / 2 {
+            return None
+        }
+
+        let new_content = format!("{}{}{}", &original_content[..start], correction, &original_content[end..]);
+        Some(ParsedMessage::Command(Command::Edit(editing_member, secondary_message.id, new_content)))
+    }
+
+    pub fn parse_edit<'a>(message: &'a FullMessage, member_id: MemberId, system_config: &System) -> &'a str {
+        // Strip the proxy tags if the edit kept them, otherwise it was autoproxied
+        system_config.members.get(member_id)
+            .and_then(|member| member.matches_proxy_prefix(message))
+            .unwrap_or(message.content.as_str())
+    }
+
+    pub fn parse_reaction(reaction: &GatewayReaction, system_config: &System, latch_state: &LatchState) -> ParsedMessage {
+        let latch_state = latch_state
+            .get(&system_config.latch_scope(reaction.guild_id, reaction.channel_id))
+            .copied();
+
+        if let Some((member_id, _)) = latch_state {
+            ParsedMessage::EmoteAdd(member_id, reaction.message_id, reaction.emoji.clone().into())
+        } else {
+            ParsedMessage::UnproxiedMessage(None)
+        }
+    }
+
+    fn check_reaction(message: &FullMessage, secondary_message: Option<&FullMessage>, secondary_member: Option<MemberId>, latch_state: Option<(MemberId, Timestamp)>) -> Option<ParsedMessage> {
+        let captures = REACTION_REGEX.captures(message.content.as_str())?;
+        let secondary_message = secondary_message?;
+
+        // React as whoever is latched, or else whoever sent the message being reacted to
+        let member_id = latch_state.map(|(member_id, _)| member_id).or(secondary_member)?;
+
+        let emoji = if let Some(unicode) = captures.name("unicode") {
+            Emoji::Unicode(unicode.as_str().to_string())
+        } else {
+            Emoji::Custom(
+                captures.name("id")?.as_str().parse().ok()?,
+                captures.name("name").map(|name| name.as_str().to_string()),
+            )
+        };
+
+        if &captures["action"] == "+" {
+            Some(ParsedMessage::EmoteAdd(member_id, secondary_message.id, emoji))
+        } else {
+            Some(ParsedMessage::EmoteRemove(member_id, secondary_message.id, emoji))
+        }
+    }
+
+    fn check_pluralkit(message: &FullMessage, system_config: &System) -> Option<ParsedMessage> {
+        let pluralkit = system_config.pluralkit.as_ref()?;
+        let captures = pluralkit.message_pattern.captures(message.content.as_str())?;
+
+        // If the pattern captures a member name we know, PluralKit switched to them
+        let member_id = captures.name("member").and_then(|member_name| {
+            system_config.members.iter().position(|member| {
+                member.name.eq_ignore_ascii_case(member_name.as_str())
+                    || member.display_name.as_ref().is_some_and(|name| name.eq_ignore_ascii_case(member_name.as_str()))
+            })
+        });
+
+        Some(ParsedMessage::LeaveForPluralkit(member_id))
     }
 
     fn check_member_patterns(message: &FullMessage, secondary_message: Option<&FullMessage>, system_config: &System) -> Option<ParsedMessage> {
@@ -207,6 +342,9 @@ impl MessageParser {
             if let Ok(mention) = Id::<UserMarker>::parse(maybe_mention) {
                 return MessageParser::get_member_id_from_user_id(mention, system_config)
             }
+
+            return system_config.members.iter()
+                .position(|member| member.name.eq_ignore_ascii_case(maybe_mention))
         }
 
         None
@@ -220,15 +358,213 @@ impl MessageParser {
     }
 }
 
+// Optimal string alignment distance, where swapping two neighbouring letters counts as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // distances[i][j] is the distance between the first i chars of a and the first j of b
+    let mut distances: Vec<Vec<usize>> = (0..=a.len())
+        .map(|i| (0..=b.len()).map(|j| if i == 0 { j } else if j == 0 { i } else { 0 }).collect())
+        .collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let insertion = distances[i][j - 1] + 1;
+            let deletion = distances[i - 1][j] + 1;
+            let mut distance = substitution.min(insertion).min(deletion);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
 impl crate::config::Member {
     pub fn matches_proxy_prefix<'a>(&self, message: &'a FullMessage) -> Option<&'a str> {
-        match self.message_pattern.captures(message.content.as_str()) {
+        let message_pattern = self.message_pattern.as_ref()?;
+
+        match message_pattern.captures(message.content.as_str()) {
             None => None,
-            Some(captures) => match captures.name("content") {
-                None => None,
-                Some(matched_content) => Some(matched_content.as_str()),
-            },
+
+            // Patterns generated from several proxy tags have one content group per
+            // alternative (content, content1, ...) since group names must be unique
+            Some(captures) => message_pattern
+                .capture_names()
+                .flatten()
+                .filter(|name| is_content_group(name))
+                .find_map(|name| captures.name(name))
+                .map(|matched_content| matched_content.as_str()),
+        }
+    }
+}
+
+
+impl System {
+    pub fn latch_scope(&self, server_id: Option<ServerId>, channel_id: ChannelId) -> LatchScope {
+        let scope = match &self.autoproxy {
+            Some(AutoproxyConfig::Latch { scope, .. }) => *scope,
+            _ => AutoProxyScope::Global,
+        };
+
+        match (scope, server_id) {
+            (AutoProxyScope::Global, _) => LatchScope::Global,
+            (AutoProxyScope::Server, Some(server_id)) => LatchScope::Server(server_id),
+
+            // Direct messages don't belong to a server, so latch them per channel
+            (AutoProxyScope::Server, None) => LatchScope::Channel(channel_id),
+            (AutoProxyScope::Channel, _) => LatchScope::Channel(channel_id),
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn test_system() -> System {
+        toml::from_str(r#"
+            reference_user_id = "1"
+
+            [[members]]
+            name = "Alice"
+            message_pattern = "a:(?<content>.*)"
+            discord_token = "token"
+
+            [[members]]
+            name = "Bob"
+            message_pattern = "b:(?<content>.*)"
+            discord_token = "token"
+        "#).unwrap()
+    }
+
+    fn message(id: u64, content: &str) -> FullMessage {
+        serde_json::from_value(json!({
+            "id": id.to_string(),
+            "channel_id": "500",
+            "author": { "id": "1", "username": "user", "discriminator": "0001", "avatar": null },
+            "content": content,
+            "timestamp": "2024-01-01T00:00:00.000000+00:00",
+            "edited_timestamp": null,
+            "tts": false,
+            "mention_everyone": false,
+            "mentions": [],
+            "mention_roles": [],
+            "attachments": [],
+            "embeds": [],
+            "pinned": false,
+            "type": 0,
+        })).unwrap()
+    }
+
+    // Parses a reply to a message Bob proxied
+    fn parse_after_bob(content: &str) -> ParsedMessage {
+        parse_after_bob_message("hello", content)
+    }
+
+    fn parse_after_bob_message(bob_content: &str, content: &str) -> ParsedMessage {
+        MessageParser::parse(&message(2, content), Some(&message(1, bob_content)), Some(1), &test_system(), &HashMap::new())
+    }
+
+    fn parse_with_pluralkit(content: &str) -> ParsedMessage {
+        let mut system = test_system();
+        system.pluralkit = Some(toml::from_str(r#"
+            message_pattern = 'pk;(switch (?<member>\w+)|.*)'
+            api_token = "token"
+        "#).unwrap());
+        system.members[1].display_name = Some("Robert".to_string());
+
+        MessageParser::parse(&message(2, content), None, None, &system, &HashMap::new())
+    }
+
+    #[test]
+    fn leaves_pluralkit_switches_for_pluralkit() {
+        assert!(matches!(parse_with_pluralkit("pk;switch alice"), ParsedMessage::LeaveForPluralkit(Some(0))));
+        assert!(matches!(parse_with_pluralkit("pk;switch robert"), ParsedMessage::LeaveForPluralkit(Some(1))));
+        assert!(matches!(parse_with_pluralkit("pk;switch carol"), ParsedMessage::LeaveForPluralkit(None)));
+    }
+
+    #[test]
+    fn leaves_other_pluralkit_commands_for_pluralkit() {
+        assert!(matches!(parse_with_pluralkit("pk;member list"), ParsedMessage::LeaveForPluralkit(None)));
+        assert!(matches!(parse_with_pluralkit("a:hello"), ParsedMessage::ProxiedMessage { member_id: 0, .. }));
+    }
+
+    #[test]
+    fn proxies_only_the_content_group() {
+        let mut system = test_system();
+        system.members[0].message_pattern = Some(crate::config::build_message_pattern("(?<content_warning>cw )?a:(?<content>.*)".to_string()).unwrap());
+
+        let parsed = MessageParser::parse(&message(2, "cw a:hello"), None, None, &system, &HashMap::new());
+        assert!(matches!(parsed, ParsedMessage::ProxiedMessage { member_id: 0, message_content, .. } if message_content == "hello"));
+    }
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(edit_distance("hello", "hello"), 0);
+        assert_eq!(edit_distance("helo", "hello"), 1);
+        assert_eq!(edit_distance("hallo", "hello"), 1);
+        assert_eq!(edit_distance("teh", "the"), 1);
+        assert_eq!(edit_distance("ca", "abc"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("café", "cafe"), 1);
+    }
+
+    fn corrected(content: &str) -> Option<String> {
+        match parse_after_bob_message("I think teh dog is hapy, isn't it?", content) {
+            ParsedMessage::Command(Command::Edit(1, message_id, new_content)) if message_id.get() == 1 => Some(new_content),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn corrects_the_closest_word() {
+        assert_eq!(corrected("*the").as_deref(), Some("I think the dog is hapy, isn't it?"));
+        assert_eq!(corrected("*happy").as_deref(), Some("I think teh dog is happy, isn't it?"));
+        assert_eq!(corrected("*Dog").as_deref(), Some("I think teh Dog is hapy, isn't it?"));
+    }
+
+    #[test]
+    fn consumes_corrections_that_change_nothing() {
+        assert_eq!(corrected("*dog").as_deref(), Some("I think teh dog is hapy, isn't it?"));
+    }
+
+    #[test]
+    fn leaves_unrelated_words_alone() {
+        assert!(corrected("*elephant").is_none());
+        assert!(matches!(parse_after_bob("*elephant"), ParsedMessage::UnproxiedMessage(None)));
+    }
+
+    #[test]
+    fn parses_reactions() {
+        for emoji in ["👍", "❤️", "👍🏽", "🏳️‍🌈", "🇫🇷", "⁉️"] {
+            let parsed = parse_after_bob(&format!("+{emoji}"));
+            assert!(matches!(parsed, ParsedMessage::EmoteAdd(1, id, Emoji::Unicode(ref parsed)) if id.get() == 1 && parsed == emoji), "+{emoji}");
+        }
+
+        assert!(matches!(parse_after_bob("-👍"), ParsedMessage::EmoteRemove(1, _, Emoji::Unicode(_))));
+        assert!(matches!(
+            parse_after_bob("+<:blob:123>"),
+            ParsedMessage::EmoteAdd(1, _, Emoji::Custom(id, Some(ref name))) if id.get() == 123 && name == "blob"
+        ));
+    }
+
+    #[test]
+    fn leaves_other_text_after_plus_or_minus_alone() {
+        for content in ["-é", "+日本", "+👍 nice", "+-"] {
+            assert!(matches!(parse_after_bob(content), ParsedMessage::UnproxiedMessage(None)), "{content}");
+        }
+
+        assert!(matches!(parse_after_bob("b:+日本"), ParsedMessage::ProxiedMessage { member_id: 1, .. }));
+    }
+}
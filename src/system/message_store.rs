@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::MemberName;
+use super::{ChannelId, MessageId, ServerId, Timestamp};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProxiedMessageRecord {
+    pub proxied_id: MessageId,
+    pub original_id: MessageId,
+    pub channel_id: ChannelId,
+    pub server_id: Option<ServerId>,
+    pub member: MemberName,
+    pub timestamp: Timestamp,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum StoreEntry {
+    Proxied(ProxiedMessageRecord),
+    Deleted { proxied_id: MessageId },
+}
+
+/// Append-only log of every message we've proxied, kept in memory for lookups
+pub struct MessageStore {
+    file: Option<File>,
+    records: HashMap<MessageId, ProxiedMessageRecord>,
+    originals: HashMap<MessageId, MessageId>,
+}
+
+impl MessageStore {
+    pub fn in_memory() -> Self {
+        Self {
+            file: None,
+            records: HashMap::new(),
+            originals: HashMap::new(),
+        }
+    }
+
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut store = Self::in_memory();
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                // Skip anything we can't read rather than refusing to start
+                if let Ok(entry) = serde_json::from_str::<StoreEntry>(line?.as_str()) {
+                    store.apply(entry);
+                }
+            }
+        }
+
+        store.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(store)
+    }
+
+    pub fn record(&mut self, record: ProxiedMessageRecord) -> io::Result<()> {
+        self.append(StoreEntry::Proxied(record))
+    }
+
+    pub fn mark_deleted(&mut self, proxied_id: MessageId) -> io::Result<()> {
+        if !self.records.contains_key(&proxied_id) {
+            return Ok(())
+        }
+
+        self.append(StoreEntry::Deleted { proxied_id })
+    }
+
+    pub fn by_proxied_id(&self, proxied_id: MessageId) -> Option<&ProxiedMessageRecord> {
+        self.records.get(&proxied_id)
+    }
+
+    pub fn by_original_id(&self, original_id: MessageId) -> Option<&ProxiedMessageRecord> {
+        self.originals.get(&original_id).and_then(|proxied_id| self.records.get(proxied_id))
+    }
+
+    fn append(&mut self, entry: StoreEntry) -> io::Result<()> {
+        if let Some(file) = self.file.as_mut() {
+            let mut line = serde_json::to_string(&entry)?;
+            line.push('\n');
+            file.write_all(line.as_bytes())?;
+        }
+
+        self.apply(entry);
+        Ok(())
+    }
+
+    fn apply(&mut self, entry: StoreEntry) {
+        match entry {
+            StoreEntry::Proxied(record) => {
+                self.originals.insert(record.original_id, record.proxied_id);
+                self.records.insert(record.proxied_id, record);
+            },
+            StoreEntry::Deleted { proxied_id } => {
+                if let Some(record) = self.records.remove(&proxied_id) {
+                    if self.originals.get(&record.original_id) == Some(&proxied_id) {
+                        self.originals.remove(&record.original_id);
+                    }
+                }
+            },
+        }
+    }
+}
@@ -1,59 +1,160 @@
-use std::{collections::HashMap, num::NonZeroUsize, str::FromStr, time::Duration};
+use std::{collections::HashMap, io, sync::Arc, num::NonZeroUsize, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
 
 use std::sync::mpsc::Sender as ThreadSender;
 use lru::LruCache;
+use rand::Rng;
 use tokio::{
-    sync::mpsc::{channel, Sender},
+    sync::mpsc::{Receiver, Sender},
     time::sleep,
 };
-use twilight_http::request::channel::reaction::RequestReactionType;
-use twilight_model::{channel::message::{MessageReference, MessageType, ReactionType}, id::{marker::UserMarker, Id}};
+use twilight_model::{channel::message::{MessageReference, MessageType, ReactionType}, id::marker::UserMarker};
+use twilight_model::gateway::GatewayReaction;
 use twilight_model::util::Timestamp;
 
-use crate::config::{AutoproxyConfig, AutoproxyLatchScope, Member};
+use crate::config::{AutoProxyScope, AutoproxyConfig, Member, MemberName, PluralkitConfig};
 use crate::SystemUiEvent;
 
 mod aggregator;
+mod backend;
 mod bot;
 mod types;
+mod webhook;
 mod message_parser;
+mod message_store;
+mod state;
+mod pluralkit;
+
+#[cfg(test)]
+mod fake_discord;
+#[cfg(test)]
+mod recording;
+#[cfg(test)]
+mod tests;
 
 use message_parser::MessageParser;
 use aggregator::MessageAggregator;
-use bot::Bot;
+use message_store::{MessageStore, ProxiedMessageRecord};
+use state::{SavedLatch, SavedSwitch};
+use backend::{Backend, ProxyBackend};
+use bot::{Bot, GatewayRole};
+use webhook::WebhookBackend;
 pub use types::*;
 
 use self::message_parser::{Command, ParsedMessage};
 
+// Gateway reconnects back off from the base delay up to the cap, and give up after the last retry
+const GATEWAY_RETRY_BASE: Duration = Duration::from_secs(2);
+const GATEWAY_RETRY_CAP: Duration = Duration::from_secs(300);
+const MAX_GATEWAY_RETRIES: u32 = 8;
 
-pub struct Manager {
+pub struct Manager<B = Backend> {
     pub name: String,
     pub config: crate::config::System,
-    pub bots: HashMap<MemberId, Bot>,
-    pub latch_state: Option<(MemberId, Timestamp)>,
+    pub bots: HashMap<MemberId, B>,
+    pub latch_state: LatchState,
     pub system_sender: Option<Sender<SystemEvent>>,
     pub aggregator: MessageAggregator,
     pub send_cache: LruCache<ChannelId, TwiMessage>,
+    pub reaction_cache: LruCache<(MessageId, Emoji), Instant>,
+    pub typing_cache: LruCache<ChannelId, Instant>,
+    pub message_store: MessageStore,
+    pub data_path: PathBuf,
+    pub fronters: Vec<MemberId>,
+    pub switch_history: Vec<SavedSwitch>,
     pub reference_user_id: UserId,
     pub ui_sender: ThreadSender<(String, SystemUiEvent)>,
+    pub gateway_retries: HashMap<MemberId, u32>,
 }
 
-impl Manager {
+impl<B: ProxyBackend> Manager<B> {
     pub fn new(system_name: String, system_config: crate::config::System, ui_sender : ThreadSender<(String, SystemUiEvent)>) -> Self {
+        let data_path = Path::new(&system_config.data_directory).join(&system_name);
+
+        let store_path = data_path.join("messages.jsonl");
+        let message_store = MessageStore::open(&store_path).unwrap_or_else(|err| {
+            let _ = ui_sender.send((system_name.clone(), SystemUiEvent::LogLine(
+                format!("WARNING: Could not open message store {}, proxied messages won't be remembered: {}", store_path.display(), err)
+            )));
+            MessageStore::in_memory()
+        });
+
+        let latch_path = data_path.join("latch.json");
+        let mut latch_state = Self::restore_latch_state(&latch_path, &system_config).unwrap_or_else(|err| {
+            let _ = ui_sender.send((system_name.clone(), SystemUiEvent::LogLine(
+                format!("WARNING: Could not restore latch state from {}: {}", latch_path.display(), err)
+            )));
+            HashMap::new()
+        });
+
+        let switch_path = data_path.join("switches.jsonl");
+        let switch_history = state::load_switches(&switch_path).unwrap_or_else(|err| {
+            let _ = ui_sender.send((system_name.clone(), SystemUiEvent::LogLine(
+                format!("WARNING: Could not load switch history from {}: {}", switch_path.display(), err)
+            )));
+            Vec::new()
+        });
+
+        let fronters: Vec<MemberId> = switch_history.last().map_or(Vec::new(), |switch| {
+            switch.members.iter()
+                .filter_map(|name| system_config.members.iter().position(|member| member.name == *name))
+                .collect()
+        });
+
+        // In front mode autoproxy follows the first fronter
+        if let (Some(AutoproxyConfig::Front { .. }), Some(fronter), Some(switch)) = (&system_config.autoproxy, fronters.first(), switch_history.last()) {
+            latch_state.insert(LatchScope::Global, (*fronter, switch.timestamp));
+        }
+
         Self {
-            reference_user_id: Id::from_str(&system_config.reference_user_id.as_str())
-                .expect(format!("Invalid user id for system {}", &system_name).as_str()),
+            reference_user_id: system_config.reference_user_id,
             aggregator: MessageAggregator::new(system_config.members.len()),
             name: system_name,
             config: system_config,
             bots: HashMap::new(),
-            latch_state: None,
+            latch_state,
             system_sender: None,
             send_cache: LruCache::new(NonZeroUsize::new(15).unwrap()),
+            reaction_cache: LruCache::new(NonZeroUsize::new(15).unwrap()),
+            typing_cache: LruCache::new(NonZeroUsize::new(15).unwrap()),
+            message_store,
+            data_path,
+            fronters,
+            switch_history,
             ui_sender,
+            gateway_retries: HashMap::new(),
         }
     }
 
+    fn restore_latch_state(path: &Path, system_config: &crate::config::System) -> io::Result<LatchState> {
+        let Some(AutoproxyConfig::Latch { scope, timeout_seconds, .. }) = &system_config.autoproxy else {
+            return Ok(HashMap::new())
+        };
+
+        let now = Timestamp::from_micros(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as i64).unwrap();
+        let timeout_micros = i64::from(*timeout_seconds) * 1_000_000;
+
+        Ok(state::load_latches(path)?.into_iter().filter_map(|saved| {
+            // Skip latches from a different scope setting, or that expired while we were down
+            let expired = now.as_micros() - saved.timestamp.as_micros() >= timeout_micros;
+            if !Self::scope_matches_config(scope, saved.scope) || expired {
+                return None
+            }
+
+            let member_id = system_config.members.iter().position(|member| member.name == saved.member)?;
+            Some((saved.scope, (member_id, saved.timestamp)))
+        }).collect())
+    }
+
+    // Server scope falls back to channel latches in DMs, see System::latch_scope
+    fn scope_matches_config(config_scope: &AutoProxyScope, scope: LatchScope) -> bool {
+        matches!(
+            (config_scope, scope),
+            (AutoProxyScope::Global, LatchScope::Global)
+                | (AutoProxyScope::Server, LatchScope::Server(_) | LatchScope::Channel(_))
+                | (AutoProxyScope::Channel, LatchScope::Channel(_))
+        )
+    }
+
     pub fn find_member_by_name<'a>(
         &'a self,
         name: &String,
@@ -74,18 +175,41 @@ impl Manager {
             .map_or(None, |(_member_id, member)| Some(member))
     }
 
-    pub async fn start_clients(&mut self) {
+}
+
+impl Manager<Backend> {
+    pub async fn start_clients(&mut self, system_channel: (Sender<SystemEvent>, Receiver<SystemEvent>)) -> SystemThreadCommand {
         let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
             format!("Starting clients for system {}", self.name)
         )));
 
-        let (system_sender, mut system_receiver) = channel::<SystemEvent>(100);
+        if let Some(pluralkit_config) = self.config.pluralkit.clone() {
+            self.import_pluralkit_members(&pluralkit_config).await;
+        }
+
+        let (system_sender, mut system_receiver) = system_channel;
         self.system_sender = Some(system_sender.clone());
+
+        // Pick up the timeouts of any latches restored from before a restart
+        if let Some(AutoproxyConfig::Latch { timeout_seconds, .. }) = &self.config.autoproxy {
+            let timeout = Duration::from_secs((*timeout_seconds).into());
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+            for (scope, (_member, timestamp)) in self.latch_state.clone() {
+                let elapsed = now.saturating_sub(Duration::from_micros(timestamp.as_micros() as u64));
+                self.schedule_autoproxy_timeout(scope, timestamp, timeout.saturating_sub(elapsed));
+                self.latch_state_changed(scope);
+            }
+        }
         self.aggregator.set_system_handler(system_sender.clone()).await;
         self.aggregator.start();
 
-        for member_id in 0..self.config.members.len() {
-            self.start_bot(member_id).await;
+        if self.config.webhook.is_some() {
+            self.start_webhook_listener(None).await;
+        } else {
+            for member_id in 0..self.config.members.len() {
+                self.start_bot(member_id, None).await;
+            }
         }
 
         if self.config.members.len() < 1 {
@@ -96,7 +220,22 @@ impl Manager {
 
         loop {
             match system_receiver.recv().await {
+                Some(SystemEvent::GatewayConnected(member_id, _)) if self.config.webhook.is_some() => {
+                    self.gateway_retries.remove(&member_id);
+
+                    // The listener speaks for every member, but its user isn't any of theirs
+                    for member in self.config.members.iter() {
+                        let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::GatewayConnect(member.name.clone())));
+                    }
+
+                    let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                        "Webhook listener connected".to_string()
+                    )));
+                }
+
                 Some(SystemEvent::GatewayConnected(member_id, user_id)) => {
+                    self.gateway_retries.remove(&member_id);
+
                     self.config.members.iter_mut().enumerate()
                         .find(|(id, _)| *id == member_id).unwrap().1.user_id = Some(user_id);
 
@@ -107,30 +246,89 @@ impl Manager {
                     let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
                         format!("Gateway client {} ({}) connected", member.name, member_id)
                     )));
+
+                    let status = self.status_of_member(member_id);
+                    self.update_status_of_member(member_id, status).await;
                 }
 
                 Some(SystemEvent::GatewayError(member_id, message)) => {
-                    let member = self.find_member_by_id(member_id).unwrap();
-
                     let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
-                        format!("Gateway client {} ran into error {}", member.name, message)
+                        format!("Gateway client {} ran into error {}", self.gateway_client_name(member_id), message)
                     )));
                 }
 
-                Some(SystemEvent::GatewayClosed(member_id)) => {
-                    let member = self.find_member_by_id(member_id).unwrap();
+                Some(SystemEvent::GatewayClosed(member_id, resume)) => {
+                    for member_name in self.gateway_member_names(member_id) {
+                        let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::GatewayDisconnect(member_name)));
+                    }
 
-                    let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::GatewayDisconnect(member.name.clone())));
+                    let retries = self.gateway_retries.entry(member_id).or_default();
+                    *retries += 1;
+                    let retries = *retries;
+
+                    if retries > MAX_GATEWAY_RETRIES {
+                        self.gateway_failed(member_id, format!("still not connected after {MAX_GATEWAY_RETRIES} retries"));
+                        continue;
+                    }
 
+                    let delay = reconnect_delay(retries);
                     let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
-                        format!("Gateway client {} closed", member.name)
+                        format!("Gateway client {} closed, retrying in {}s ({}/{})",
+                            self.gateway_client_name(member_id), delay.as_secs(), retries, MAX_GATEWAY_RETRIES)
                     )));
 
-                    self.start_bot(member_id).await;
+                    // Wait it out without holding up the rest of the system
+                    let rx = self.system_sender.as_ref().unwrap().clone();
+                    tokio::spawn(async move {
+                        sleep(delay).await;
+                        let _ = rx.send(SystemEvent::ReconnectGateway(member_id, resume)).await;
+                    });
+                }
+
+                Some(SystemEvent::ReconnectGateway(_, resume)) if self.config.webhook.is_some() => {
+                    self.start_webhook_listener(resume).await;
+                }
+
+                Some(SystemEvent::ReconnectGateway(member_id, resume)) => {
+                    self.start_bot(member_id, resume).await;
+                }
+
+                Some(SystemEvent::GatewayFailed(member_id, reason)) => {
+                    self.gateway_failed(member_id, reason);
+                }
+
+                Some(SystemEvent::NewCommand(command)) => {
+                    return command
                 }
 
                 Some(SystemEvent::NewMessage(event_time, message, member_id)) => {
-                    self.handle_message(message, event_time, member_id).await;
+                    if let Some(command) = self.handle_message(message, event_time, member_id).await {
+                        return command
+                    }
+                }
+
+                Some(SystemEvent::NewReaction(reaction_event)) => {
+                    let (reaction, _seen_by) = *reaction_event;
+                    self.handle_reaction(reaction).await;
+                }
+
+                Some(SystemEvent::UserTyping(server_id, channel_id)) => {
+                    self.mirror_typing(server_id, channel_id).await;
+                }
+
+                Some(SystemEvent::MemberPinged(_, message)) if self.config.gateway_listener.is_some() => {
+                    // The listener passes on every mention, so work out which members were pinged
+                    let pinged_members: Vec<MemberId> = message.mentions.iter()
+                        .filter_map(|mention| MessageParser::get_member_id_from_user_id(mention.id, &self.config))
+                        .collect();
+
+                    for member_id in pinged_members {
+                        self.forward_ping(member_id, message.clone()).await;
+                    }
+                }
+
+                Some(SystemEvent::MemberPinged(member_id, message)) => {
+                    self.forward_ping(member_id, message).await;
                 }
 
                 Some(SystemEvent::RefetchMessage(member_id, message_id, channel_id)) => {
@@ -138,32 +336,51 @@ impl Manager {
                     bot.resend_message(message_id, channel_id).await;
                 }
 
-                Some(SystemEvent::AutoproxyTimeout(time_scheduled)) => {
-                    if let Some((_member, current_last_message)) = self.latch_state.clone() {
+                Some(SystemEvent::AutoproxyTimeout(scope, time_scheduled)) => {
+                    if let Some((_member, current_last_message)) = self.latch_state.get(&scope).copied() {
                         if current_last_message == time_scheduled {
-                            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::MemberAutoproxy(None)));
-
                             let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
-                                format!("Autoproxy timeout has expired: {} (last sent), {} (timeout scheduled)", current_last_message.as_secs(), time_scheduled.as_secs())
+                                format!("Autoproxy timeout has expired for {:?}: {} (last sent), {} (timeout scheduled)", scope, current_last_message.as_secs(), time_scheduled.as_secs())
                             )));
-                            self.latch_state = None;
+                            self.latch_state.remove(&scope);
+                            self.latch_state_changed(scope);
                             self.update_status_of_system().await;
                         }
                     }
                 },
 
-                Some(SystemEvent::UpdateClientStatus(member_id)) => {
-                    let bot = self.bots.get(&member_id).unwrap();
+                Some(SystemEvent::SetLatch(scope, member_name)) => {
+                    self.set_latch(scope, member_name).await;
+                },
 
-                    // TODO: handle other presence modes
-                    if let Some((latched_id, _)) = self.latch_state {
-                        if latched_id == member_id {
-                            bot.set_status(Status::Online).await;
-                            continue
-                        }
+                Some(SystemEvent::ClearLatch(scope)) => {
+                    if self.latch_state.remove(&scope).is_some() {
+                        let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                            format!("Cleared autoproxy latch for {scope}")
+                        )));
+                        self.latch_state_changed(scope);
+                        self.update_status_of_system().await;
                     }
+                },
 
-                    bot.set_status(Status::Invisible).await;
+                Some(SystemEvent::ClearAllLatches) => {
+                    let scopes: Vec<LatchScope> = self.latch_state.drain().map(|(scope, _)| scope).collect();
+
+                    for scope in scopes.iter() {
+                        let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                            format!("Cleared autoproxy latch for {scope}")
+                        )));
+                        self.latch_state_changed(*scope);
+                    }
+
+                    if !scopes.is_empty() {
+                        self.update_status_of_system().await;
+                    }
+                },
+
+                Some(SystemEvent::UpdateClientStatus(member_id)) => {
+                    let status = self.status_of_member(member_id);
+                    self.update_status_of_member(member_id, status).await;
                 }
 
                 _ => continue,
@@ -171,18 +388,40 @@ impl Manager {
         }
     }
 
-    async fn start_bot(&mut self, member_id: MemberId) {
+    async fn import_pluralkit_members(&mut self, pluralkit_config: &PluralkitConfig) {
+        match pluralkit::fetch_members(pluralkit_config).await {
+            Ok(pluralkit_members) => {
+                for log_line in pluralkit::merge_members(&mut self.config, pluralkit_members) {
+                    let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(log_line)));
+                }
+            },
+            Err(err) => {
+                let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                    format!("Could not import members from PluralKit: {err}")
+                )));
+            },
+        }
+    }
+
+    async fn start_bot(&mut self, member_id: MemberId, resume: Option<GatewayResume>) {
         let member = self.find_member_by_id(member_id).unwrap();
+        let discord_token = member.discord_token.as_ref().expect("Member has no discord token");
+
+        let role = match &self.config.gateway_listener {
+            None => GatewayRole::Member,
+            Some(listener) if *listener == member.name => GatewayRole::SystemListener,
+            Some(_) => GatewayRole::PresenceOnly,
+        };
 
         // Create gateway listener
-        let mut bot = Bot::new(member_id, &member, self.reference_user_id);
+        let mut bot = Bot::new(member_id, role, resume, discord_token, member.status.clone(), self.reference_user_id, &self.config.discord);
 
         bot.set_message_handler(self.aggregator.get_sender().await).await;
         bot.set_system_handler(self.system_sender.as_ref().unwrap().clone()).await;
 
         // Start gateway listener
-        bot.start();
-        self.bots.insert(member_id, bot);
+        bot.start().await;
+        self.bots.insert(member_id, Backend::Bot(Box::new(bot)));
 
         // Schedule status update after a few seconds
         let rx = self.system_sender.as_ref().unwrap().clone();
@@ -192,7 +431,68 @@ impl Manager {
         });
     }
 
-    async fn handle_message(&mut self, message: TwiMessage, timestamp: Timestamp, seen_by: MemberId) {
+    async fn start_webhook_listener(&mut self, resume: Option<GatewayResume>) {
+        // Every member shares the listener, so reconnecting it once keeps their webhooks cached
+        if let Some(Backend::Webhook(webhook)) = self.bots.values().next() {
+            webhook.reconnect_listener(resume).await;
+            return
+        }
+
+        let webhook_config = self.config.webhook.as_ref().expect("System doesn't use webhooks");
+
+        // The listener's events come in as if seen by the first member
+        let mut listener = Bot::new(0, GatewayRole::SystemListener, resume, &webhook_config.listener_token, None, self.reference_user_id, &self.config.discord);
+
+        listener.set_message_handler(self.aggregator.get_sender().await).await;
+        listener.set_system_handler(self.system_sender.as_ref().unwrap().clone()).await;
+        listener.start().await;
+
+        let listener = Arc::new(listener);
+        for (member_id, member) in self.config.members.iter().enumerate() {
+            self.bots.insert(member_id, Backend::Webhook(WebhookBackend::new(member, &listener)));
+        }
+    }
+
+    fn gateway_client_name(&self, member_id: MemberId) -> String {
+        match self.config.webhook {
+            Some(_) => "webhook listener".to_string(),
+            None => self.find_member_by_id(member_id).unwrap().name.clone(),
+        }
+    }
+
+    // The webhook listener speaks for every member
+    fn gateway_member_names(&self, member_id: MemberId) -> Vec<MemberName> {
+        match self.config.webhook {
+            Some(_) => self.config.members.iter().map(|member| member.name.clone()).collect(),
+            None => vec![self.find_member_by_id(member_id).unwrap().name.clone()],
+        }
+    }
+
+    // Leaves the client disconnected until the system is restarted
+    fn gateway_failed(&self, member_id: MemberId, reason: String) {
+        for member_name in self.gateway_member_names(member_id) {
+            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::GatewayFailed(member_name)));
+        }
+
+        let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+            format!("Gateway client {} failed, not reconnecting: {}", self.gateway_client_name(member_id), reason)
+        )));
+    }
+}
+
+impl<B: ProxyBackend> Manager<B> {
+    async fn handle_message(&mut self, message: TwiMessage, timestamp: Timestamp, seen_by: MemberId) -> Option<SystemThreadCommand> {
+        // Edits to a message we already proxied should update the proxy, not send another one
+        if message.edited_timestamp.is_some() {
+            let proxied = self.message_store.by_original_id(message.id)
+                .and_then(|record| Some((record.proxied_id, self.find_member_by_name(&record.member)?.0)));
+
+            if let Some((proxied_id, member_id)) = proxied {
+                self.edit_proxied_message(&message, proxied_id, member_id).await;
+                return None
+            }
+        }
+
         let bot = self.bots.get(&seen_by).expect("No client for member");
 
         // If message type is reply, use that
@@ -207,9 +507,10 @@ impl Manager {
                 let system_bot_ids : Vec<UserId> = self.config.members.iter().filter_map(|m| m.user_id).collect();
                 let recent_messages = bot.fetch_recent_channel_messages(message.channel_id).await;
 
+                // Webhook messages don't have a member's user as author, but we'll have stored them
                 let last_in_channel = recent_messages.map(|messages| {
                     messages.into_iter().filter(|message|
-                        system_bot_ids.contains(&message.author.id)
+                        system_bot_ids.contains(&message.author.id) || self.message_store.by_proxied_id(message.id).is_some()
                     ).max_by_key(|message| message.timestamp.as_micros())
                 }).ok().flatten();
 
@@ -227,7 +528,9 @@ impl Manager {
             }
         };
 
-        let parsed_message = MessageParser::parse(&message, referenced_message, &self.config, self.latch_state);
+        let latch_scope = self.config.latch_scope(message.guild_id, message.channel_id);
+        let referenced_member = referenced_message.and_then(|referenced| Self::member_of_message(&self.message_store, &self.config, referenced));
+        let parsed_message = MessageParser::parse(&message, referenced_message, referenced_member, &self.config, &self.latch_state);
 
         match parsed_message {
             message_parser::ParsedMessage::UnproxiedMessage(log_string) => if let Some(log_string) = log_string {
@@ -238,21 +541,42 @@ impl Manager {
 
             message_parser::ParsedMessage::LatchClear(member_id) => {
                 let _ = self.bots.get(&member_id).unwrap().delete_message(message.channel_id, message.id).await;
-                self.latch_state = None;
+
+                // Front autoproxy follows the fronters, so clearing it means switching out
+                if let Some(AutoproxyConfig::Front { .. }) = &self.config.autoproxy {
+                    self.switch_fronters(Vec::new(), message.timestamp).await;
+                    return None
+                }
+
+                self.latch_state.remove(&latch_scope);
                 self.update_status_of_system().await;
-                let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::MemberAutoproxy(None)));
+                self.latch_state_changed(latch_scope);
+            },
+
+            message_parser::ParsedMessage::LeaveForPluralkit(member_id) => {
+                // PluralKit may autoproxy what follows, so drop our latch rather than proxy it as well
+                if let Some(member_id) = member_id {
+                    let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                        format!("Left a switch to {} for PluralKit", self.find_member_by_id(member_id).unwrap().name)
+                    )));
+                }
+
+                if self.latch_state.remove(&latch_scope).is_some() {
+                    self.latch_state_changed(latch_scope);
+                    self.update_status_of_system().await;
+                }
             },
 
             message_parser::ParsedMessage::SetProxyAndDelete(member_id) => {
                 let _ = self.bots.get(&member_id).unwrap().delete_message(message.channel_id, message.id).await;
-                self.update_autoproxy_state_after_message(member_id, message.timestamp);
+                self.update_autoproxy_state_after_message(member_id, message.timestamp, latch_scope);
                 self.update_status_of_system().await;
             }
 
             message_parser::ParsedMessage::ProxiedMessage { member_id, message_content, latch } => {
                 if let Ok(_) = self.proxy_message(&message, member_id, message_content.as_str()).await {
                     if latch {
-                        self.update_autoproxy_state_after_message(member_id, timestamp);
+                        self.update_autoproxy_state_after_message(member_id, timestamp, latch_scope);
                         self.update_status_of_system().await;
                     }
                 }
@@ -261,13 +585,19 @@ impl Manager {
             message_parser::ParsedMessage::Command(Command::Edit(member_id, message_id, new_content)) => {
                 let bot = self.bots.get(&member_id).unwrap();
 
-                let author = MessageParser::get_member_id_from_user_id(referenced_message.unwrap().author.id, &self.config);
+                let author = Self::member_of_message(&self.message_store, &self.config, referenced_message.unwrap());
                 if author.is_none() {
                     let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
                         format!("Cannot edit another user's message")
                     )));
-                    let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &RequestReactionType::Unicode { name: "🛑" }).await;
-                    return
+                    let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &Emoji::Unicode("🛑".to_string())).await;
+                    return None
+                }
+
+                // A correction to a word that was already right, so there's only the command to clean up
+                if referenced_message.is_some_and(|referenced| referenced.content == new_content) {
+                    let _ = bot.delete_message(message.channel_id, message.id).await;
+                    return None
                 }
 
                 if let Ok(new_message) = bot.edit_message(message.channel_id, message_id, new_content).await {
@@ -288,24 +618,24 @@ impl Manager {
                     let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
                         format!("ERROR: Attempted reproxy on message other than referenced_message")
                     )));
-                    let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &RequestReactionType::Unicode { name: "⁉️" }).await;
-                    return
+                    let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &Emoji::Unicode("⁉️".to_string())).await;
+                    return None
                 }
 
-                let author = MessageParser::get_member_id_from_user_id(referenced_message.unwrap().author.id, &self.config);
+                let author = Self::member_of_message(&self.message_store, &self.config, referenced_message.unwrap());
                 if author.is_none() {
                     let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
                         format!("Cannot reproxy another user's message")
                     )));
-                    let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &RequestReactionType::Unicode { name: "🛑" }).await;
-                    return
+                    let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &Emoji::Unicode("🛑".to_string())).await;
+                    return None
                 }
 
                 if author.unwrap() != member_id {
                     // TODO: Don't allow this if other messages have been sent maybe?
                     let orig = referenced_message.unwrap().clone();
                     if let Ok(_) = self.proxy_message(&orig, member_id, orig.content.as_str()).await {
-                        self.update_autoproxy_state_after_message(member_id, timestamp);
+                        self.update_autoproxy_state_after_message(member_id, timestamp, latch_scope);
                         self.update_status_of_system().await;
                     }
                 } else {
@@ -319,19 +649,81 @@ impl Manager {
             }
 
             message_parser::ParsedMessage::Command(Command::Delete(message_id)) => {
-                let member_id = self.latch_state.map(|(id,_)| id).unwrap_or(0);
+                let member_id = self.latch_state.get(&latch_scope).map(|(id,_)| *id).unwrap_or(0);
 
-                let author = MessageParser::get_member_id_from_user_id(referenced_message.unwrap().author.id, &self.config);
+                let author = Self::member_of_message(&self.message_store, &self.config, referenced_message.unwrap());
                 if author.is_none() {
                     let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
                         format!("Cannot delete another user's message")
                     )));
-                    let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &RequestReactionType::Unicode { name: "🛑" }).await;
-                    return
+                    let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &Emoji::Unicode("🛑".to_string())).await;
+                    return None
                 }
 
-                let bot = self.bots.get(&member_id).unwrap();
-                let _ = bot.delete_message(message.channel_id, message_id).await;
+                // Delete as the member who sent it, in case the others lack permissions
+                let bot = self.bots.get(&author.unwrap()).unwrap();
+                let delete_result = bot.delete_message(message.channel_id, message_id).await;
+                let _ = bot.delete_message(message.channel_id, message.id).await;
+
+                if delete_result.is_ok() {
+                    self.record_deletion(message_id);
+                }
+            }
+
+            message_parser::ParsedMessage::Command(Command::Switch(member_ids)) => {
+                self.switch_fronters(member_ids, message.timestamp).await;
+
+                let bot = self.fronting_bot()?;
+                let _ = bot.delete_message(message.channel_id, message.id).await;
+            }
+
+            message_parser::ParsedMessage::Command(Command::Front) => {
+                let response = match self.switch_history.last() {
+                    Some(switch) if !switch.members.is_empty() => format!(
+                        "Currently fronting: {} (since <t:{}:R>)",
+                        self.fronter_names(&switch.members),
+                        switch.timestamp.as_secs(),
+                    ),
+                    _ => "Nobody is fronting".to_string(),
+                };
+
+                let bot = self.fronting_bot()?;
+                let _ = bot.send_message(message.channel_id, response.as_str()).await;
+                let _ = bot.delete_message(message.channel_id, message.id).await;
+            }
+
+            message_parser::ParsedMessage::Command(Command::FrontHistory) => {
+                let lines: Vec<String> = self.switch_history.iter().rev().take(10).map(|switch| {
+                    let fronters = if switch.members.is_empty() {
+                        "(switched out)".to_string()
+                    } else {
+                        self.fronter_names(&switch.members)
+                    };
+
+                    format!("<t:{}:f>: {}", switch.timestamp.as_secs(), fronters)
+                }).collect();
+
+                // Leave off the oldest switches rather than go over Discord's limit
+                let mut response = String::new();
+                for line in lines {
+                    let separator = if response.is_empty() { "" } else { "\n" };
+                    if response.chars().count() + separator.len() + line.chars().count() > MAX_CONTENT_LENGTH {
+                        if response.is_empty() {
+                            response = line.chars().take(MAX_CONTENT_LENGTH).collect();
+                        }
+                        break
+                    }
+
+                    response.push_str(separator);
+                    response.push_str(&line);
+                }
+
+                if response.is_empty() {
+                    response = "No switches recorded".to_string();
+                }
+
+                let bot = self.fronting_bot()?;
+                let _ = bot.send_message(message.channel_id, response.as_str()).await;
                 let _ = bot.delete_message(message.channel_id, message.id).await;
             }
 
@@ -350,17 +742,139 @@ impl Manager {
             }
 
             message_parser::ParsedMessage::Command(Command::UnknownCommand) => {
-                let member_id = if let Some((member_id, _)) = self.latch_state {
+                let member_id = if let Some((member_id, _)) = self.latch_state.get(&latch_scope).copied() {
                     member_id
                 } else {
                     0
                 };
 
-                let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &RequestReactionType::Unicode { name: "⁉️" }).await;
+                let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &Emoji::Unicode("⁉️".to_string())).await;
+            },
+            message_parser::ParsedMessage::Command(Command::InvalidCommand) => {
+                let member_id = self.latch_state.get(&latch_scope).map(|(id, _)| *id).unwrap_or(0);
+                let _ = self.bots.get(&member_id).unwrap().react_message(message.channel_id, message.id, &Emoji::Unicode("⁉️".to_string())).await;
+            },
+
+            message_parser::ParsedMessage::Command(command @ (Command::ReloadSystemConfig | Command::ExitSéance)) => {
+                let member_id = self.latch_state.get(&latch_scope).map(|(id, _)| *id).unwrap_or(0);
+                let bot = self.bots.get(&member_id);
+
+                if message.author.id != self.reference_user_id {
+                    let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                        format!("Ignoring system command from user {}", message.author.id)
+                    )));
+                    if let Some(bot) = bot {
+                        let _ = bot.react_message(message.channel_id, message.id, &Emoji::Unicode("🛑".to_string())).await;
+                    }
+                    return None
+                }
+
+                // The command still goes ahead, there's just nobody to clean it up
+                if let Some(bot) = bot {
+                    let _ = bot.delete_message(message.channel_id, message.id).await;
+                }
+
+                return Some(match command {
+                    Command::ReloadSystemConfig => SystemThreadCommand::ReloadConfig,
+                    _ => {
+                        let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                            format!("Exiting séance, !exit was sent in channel {}", message.channel_id)
+                        )));
+                        SystemThreadCommand::ShutdownAll
+                    },
+                })
+            },
+
+            message_parser::ParsedMessage::EmoteAdd(member_id, message_id, emoji) => {
+                let bot = self.bots.get(&member_id).unwrap();
+                let _ = bot.react_message(message.channel_id, message_id, &emoji).await;
+                let _ = bot.delete_message(message.channel_id, message.id).await;
+            },
+
+            message_parser::ParsedMessage::EmoteRemove(member_id, message_id, emoji) => {
+                let bot = self.bots.get(&member_id).unwrap();
+                let _ = bot.unreact_message(message.channel_id, message_id, &emoji).await;
+                let _ = bot.delete_message(message.channel_id, message.id).await;
             },
-            message_parser::ParsedMessage::Command(_) => todo!(),
-            message_parser::ParsedMessage::EmoteAdd(_, _, _) => todo!(),
-            message_parser::ParsedMessage::EmoteRemove(_, _, _) => todo!(),
+        }
+
+        None
+    }
+
+    async fn handle_reaction(&mut self, reaction: GatewayReaction) {
+        // Every member's gateway sees the same reaction, so only act on the first one
+        let cache_key = (reaction.message_id, Emoji::from(reaction.emoji.clone()));
+        if self.reaction_cache.get(&cache_key).is_some_and(|seen| seen.elapsed() < Duration::from_secs(5)) {
+            return
+        }
+        self.reaction_cache.put(cache_key, Instant::now());
+
+        if let ParsedMessage::EmoteAdd(member_id, message_id, emoji) = MessageParser::parse_reaction(&reaction, &self.config, &self.latch_state) {
+            let bot = self.bots.get(&member_id).unwrap();
+
+            match bot.react_message(reaction.channel_id, message_id, &emoji).await {
+                // Removing the user's reaction fires a ReactionRemove event we can't tell apart
+                // from the user removing it themselves, which is why removals aren't mirrored
+                Ok(()) => {
+                    let _ = bot.remove_reaction(reaction.channel_id, message_id, &emoji, self.reference_user_id).await;
+                },
+
+                // Leave the user's reaction in place rather than losing it
+                Err(err) => {
+                    let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                        format!("WARNING: Could not react to message {message_id}: {err}")
+                    )));
+                },
+            }
+        }
+    }
+
+    async fn mirror_typing(&mut self, server_id: Option<ServerId>, channel_id: ChannelId) {
+        // Typing lasts about ten seconds, and every member's gateway reports the same event,
+        // so don't trigger it again until the last one is about to run out
+        if self.typing_cache.get(&channel_id).is_some_and(|last_typed| last_typed.elapsed() < Duration::from_secs(8)) {
+            return
+        }
+
+        let latch_scope = self.config.latch_scope(server_id, channel_id);
+        if let Some((member_id, _)) = self.latch_state.get(&latch_scope).copied() {
+            self.typing_cache.put(channel_id, Instant::now());
+
+            let bot = self.bots.get(&member_id).unwrap();
+            let _ = bot.trigger_typing(channel_id).await;
+        }
+    }
+
+    async fn forward_ping(&self, member_id: MemberId, message: TwiMessage) {
+        // Our own proxied messages mentioning each other aren't worth a notification. With webhooks
+        // nobody can mention a member, only the listener bot.
+        if !self.config.forward_pings || self.config.webhook.is_some() || MessageParser::get_member_id_from_user_id(message.author.id, &self.config).is_some() {
+            return
+        }
+
+        let member = self.find_member_by_id(member_id).unwrap();
+        let server = message.guild_id.map_or("@me".to_string(), |server_id| server_id.to_string());
+        let mut preview: String = message.content.chars().take(200).collect();
+        if preview.len() < message.content.len() {
+            preview.push('…');
+        }
+
+        let notification = format!(
+            "{} mentioned {} in <#{}>: https://discord.com/channels/{}/{}/{}\n> {}",
+            message.author.name,
+            member.display_name.as_ref().unwrap_or(&member.name),
+            message.channel_id,
+            server,
+            message.channel_id,
+            message.id,
+            preview.replace('\n', "\n> "),
+        );
+
+        let bot = self.bots.get(&member_id).unwrap();
+        if let Err(err) = bot.send_direct_message(self.reference_user_id, notification.as_str()).await {
+            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                format!("Could not forward ping for {}: {}", member.name, err)
+            )));
         }
     }
 
@@ -391,84 +905,253 @@ impl Manager {
 
         // Sent successfully, add to send cache
         let sent_message = duplicate_result.unwrap();
+
+        // When reproxying, keep pointing at the message the user originally sent
+        let original_id = self.message_store.by_proxied_id(message.id)
+            .map_or(message.id, |record| record.original_id);
+        self.record_deletion(message.id);
+
+        let store_result = self.message_store.record(ProxiedMessageRecord {
+            proxied_id: sent_message.id,
+            original_id,
+            channel_id: sent_message.channel_id,
+            server_id: message.guild_id,
+            member: self.find_member_by_id(member).unwrap().name.clone(),
+            timestamp: sent_message.timestamp,
+        });
+
+        if let Err(err) = store_result {
+            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                format!("Could not store proxied message: {err}")
+            )));
+        }
+
         self.send_cache.put(sent_message.channel_id, sent_message);
 
         Ok(())
     }
 
-    fn update_autoproxy_state_after_message(&mut self, member: MemberId, timestamp: Timestamp) {
+    fn record_deletion(&mut self, proxied_id: MessageId) {
+        if let Err(err) = self.message_store.mark_deleted(proxied_id) {
+            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                format!("Could not store message deletion: {err}")
+            )));
+        }
+    }
+
+    fn member_of_message(message_store: &MessageStore, system_config: &crate::config::System, message: &TwiMessage) -> Option<MemberId> {
+        message_store.by_proxied_id(message.id)
+            .and_then(|record| system_config.members.iter().position(|member| member.name == record.member))
+            .or_else(|| MessageParser::get_member_id_from_user_id(message.author.id, system_config))
+    }
+
+    async fn edit_proxied_message(&mut self, message: &TwiMessage, proxied_id: MessageId, member_id: MemberId) {
+        let bot = self.bots.get(&member_id).expect("No client for member");
+        let new_content = MessageParser::parse_edit(message, member_id, &self.config);
+
+        match bot.edit_message(message.channel_id, proxied_id, new_content.to_string()).await {
+            Ok(new_message) => {
+                if self.send_cache.get(&new_message.channel_id).map_or(false, |m| m.id == proxied_id) {
+                    self.send_cache.put(new_message.channel_id, new_message);
+                }
+            },
+            Err(err) => {
+                let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                    format!("Could not edit proxied message {}: {:?}", proxied_id, err)
+                )));
+            },
+        }
+    }
+
+    fn update_autoproxy_state_after_message(&mut self, member: MemberId, timestamp: Timestamp, scope: LatchScope) {
         match &self.config.autoproxy {
             None => (),
             Some(AutoproxyConfig::Member { name: _ }) => (),
+
+            // Front mode only changes on switches
+            Some(AutoproxyConfig::Front { presence_indicator: _ }) => (),
             Some(AutoproxyConfig::Latch {
-                scope,
+                scope: _,
                 timeout_seconds,
                 presence_indicator: _,
             }) => {
-                self.latch_state = Some((member, timestamp));
+                let timeout = Duration::from_secs((*timeout_seconds).into());
+                self.latch_state.insert(scope, (member, timestamp));
+                self.latch_state_changed(scope);
+                self.schedule_autoproxy_timeout(scope, timestamp, timeout);
+            }
+        }
+    }
 
-                let member = self.find_member_by_id(member).unwrap();
-                let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::MemberAutoproxy(Some(member.name.clone()))));
+    async fn set_latch(&mut self, scope: LatchScope, member_name: MemberName) {
+        let Some(AutoproxyConfig::Latch { scope: config_scope, .. }) = &self.config.autoproxy else {
+            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                "Can't set a latch, autoproxy is not in latch mode".to_string()
+            )));
+            return
+        };
 
-                if let Some(channel) = self.system_sender.clone() {
-                    let last_message = timestamp.clone();
-                    let timeout_seconds = timeout_seconds.clone();
+        if !Self::scope_matches_config(config_scope, scope) {
+            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                format!("Can't set a latch for {scope}, it doesn't match the configured latch scope")
+            )));
+            return
+        }
 
-                    tokio::spawn(async move {
-                        sleep(Duration::from_secs(timeout_seconds.into())).await;
-                        channel
-                            .send(SystemEvent::AutoproxyTimeout(last_message))
-                            .await
-                            .expect("Channel has closed");
-                    });
+        let Some((member_id, _)) = self.find_member_by_name(&member_name) else {
+            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                format!("Can't set a latch, no member named {member_name}")
+            )));
+            return
+        };
+
+        let now = Timestamp::from_micros(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as i64).unwrap();
+
+        let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+            format!("Latched {member_name} for {scope}")
+        )));
+        self.update_autoproxy_state_after_message(member_id, now, scope);
+        self.update_status_of_system().await;
+    }
+
+    async fn switch_fronters(&mut self, member_ids: Vec<MemberId>, timestamp: Timestamp) {
+        let switch = SavedSwitch {
+            timestamp,
+            members: member_ids.iter().map(|member_id| self.find_member_by_id(*member_id).unwrap().name.clone()).collect(),
+        };
+
+        if let Err(err) = state::append_switch(&self.data_path.join("switches.jsonl"), &switch) {
+            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                format!("Could not save switch: {err}")
+            )));
+        }
+
+        let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+            format!("Switched to: {}", if switch.members.is_empty() { "nobody".to_string() } else { switch.members.join(", ") })
+        )));
+
+        self.switch_history.push(switch);
+        self.fronters = member_ids;
+
+        if let Some(AutoproxyConfig::Front { .. }) = &self.config.autoproxy {
+            match self.fronters.first() {
+                Some(fronter) => { self.latch_state.insert(LatchScope::Global, (*fronter, timestamp)); },
+                None => { self.latch_state.remove(&LatchScope::Global); },
+            }
+
+            self.latch_state_changed(LatchScope::Global);
+        }
+
+        self.update_status_of_system().await;
+    }
+
+    /// Whoever is fronting answers commands, or the first member when nobody is
+    fn fronting_bot(&self) -> Option<&B> {
+        let member_id = self.fronters.first().copied().unwrap_or(0);
+        let bot = self.bots.get(&member_id);
+
+        if bot.is_none() {
+            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                format!("WARNING: No client for member {member_id} to answer the command with")
+            )));
+        }
+
+        bot
+    }
+
+    fn fronter_names(&self, member_names: &[MemberName]) -> String {
+        member_names.iter().map(|name| match self.find_member_by_name(name) {
+            Some((_, member)) => member.display_name.as_ref().unwrap_or(&member.name).clone(),
+            None => name.clone(),
+        }).collect::<Vec<_>>().join(", ")
+    }
+
+    fn schedule_autoproxy_timeout(&self, scope: LatchScope, last_message: Timestamp, timeout: Duration) {
+        if let Some(channel) = self.system_sender.clone() {
+            tokio::spawn(async move {
+                sleep(timeout).await;
+                channel
+                    .send(SystemEvent::AutoproxyTimeout(scope, last_message))
+                    .await
+                    .expect("Channel has closed");
+            });
+        }
+    }
+
+    fn most_recent_latch(&self) -> Option<(MemberId, Timestamp)> {
+        self.latch_state
+            .values()
+            .max_by_key(|(_member, timestamp)| timestamp.as_micros())
+            .copied()
+    }
+
+    fn latch_state_changed(&self, scope: LatchScope) {
+        let saved_latches: Vec<SavedLatch> = self.latch_state.iter().map(|(scope, (member_id, timestamp))| SavedLatch {
+            scope: *scope,
+            member: self.find_member_by_id(*member_id).unwrap().name.clone(),
+            timestamp: *timestamp,
+        }).collect();
+
+        if let Err(err) = state::save_latches(&self.data_path.join("latch.json"), &saved_latches) {
+            let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::LogLine(
+                format!("Could not save latch state: {err}")
+            )));
+        }
+
+        let scope_member = self.latch_state.get(&scope)
+            .and_then(|(member_id, _)| self.find_member_by_id(*member_id))
+            .map(|member| member.name.clone());
+
+        let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::ScopeAutoproxy(scope.to_string(), scope_member)));
+
+        // Show whoever was latched in the most recently active scope
+        let latched_member = self.most_recent_latch()
+            .and_then(|(member_id, _)| self.find_member_by_id(member_id))
+            .map(|member| member.name.clone());
+
+        let _ = self.ui_sender.send((self.name.clone(), SystemUiEvent::MemberAutoproxy(latched_member)));
+    }
+
+    fn status_of_member(&self, member_id: MemberId) -> Status {
+        let member = self.find_member_by_id(member_id).unwrap();
+        let configured_status = member.presence.as_ref().map(Status::from);
+
+        // Whether presence is used to indicate autoproxy, and if so whether it points at this member
+        let indicated = match &self.config.autoproxy {
+            None => None,
+            Some(AutoproxyConfig::Member { name }) => Some(member.name == *name),
+            Some(AutoproxyConfig::Front { presence_indicator }) => {
+                if !presence_indicator {
+                    None
+                } else {
+                    Some(self.fronters.contains(&member_id))
+                }
+            }
+            Some(AutoproxyConfig::Latch {
+                scope: _,
+                timeout_seconds: _,
+                presence_indicator,
+            }) => {
+                if !presence_indicator {
+                    None
+                } else {
+                    // With per-server or per-channel latching there can be several latched
+                    // members, so indicate the one from the most recently active scope
+                    Some(self.most_recent_latch().is_some_and(|(latch_member, _)| latch_member == member_id))
                 }
             }
+        };
+
+        match indicated {
+            Some(true) => configured_status.unwrap_or(Status::Online),
+            Some(false) => Status::Invisible,
+            None => configured_status.unwrap_or(Status::Invisible),
         }
     }
 
     async fn update_status_of_system(&mut self) {
-        let member_states: Vec<(MemberId, Status)> = self
-            .config
-            .members
-            .iter()
-            .enumerate()
-            .map(|(member_id, member)| {
-                (
-                    member_id,
-                    match &self.config.autoproxy {
-                        None => Status::Invisible,
-                        Some(AutoproxyConfig::Member { name }) => {
-                            if member.name == *name {
-                                Status::Online
-                            } else {
-                                Status::Invisible
-                            }
-                        }
-                        Some(AutoproxyConfig::Latch {
-                            scope,
-                            timeout_seconds: _,
-                            presence_indicator,
-                        }) => {
-                            if let AutoproxyLatchScope::Server = scope {
-                                Status::Invisible
-                            } else if !presence_indicator {
-                                Status::Invisible
-                            } else {
-                                match &self.latch_state {
-                                    Some((latch_member, _last_timestamp)) => {
-                                        if member_id == *latch_member {
-                                            Status::Online
-                                        } else {
-                                            Status::Invisible
-                                        }
-                                    }
-                                    None => Status::Invisible,
-                                }
-                            }
-                        }
-                    },
-                )
-            })
+        let member_states: Vec<(MemberId, Status)> = (0..self.config.members.len())
+            .map(|member_id| (member_id, self.status_of_member(member_id)))
             .collect();
 
         for (member, status) in member_states {
@@ -482,3 +1165,12 @@ impl Manager {
     }
 }
 
+// Exponential backoff with up to a quarter added on top, so clients don't all retry at once
+fn reconnect_delay(retries: u32) -> Duration {
+    let backoff = GATEWAY_RETRY_BASE
+        .saturating_mul(1 << retries.saturating_sub(1).min(16))
+        .min(GATEWAY_RETRY_CAP);
+
+    let jitter_millis = rand::thread_rng().gen_range(0..=backoff.as_millis() as u64 / 4);
+    backoff + Duration::from_millis(jitter_millis)
+}
@@ -0,0 +1,234 @@
+use std::time::Duration;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::config::{build_message_pattern, PluralkitConfig, System};
+
+#[derive(Deserialize)]
+pub struct PluralkitMember {
+    pub id: String,
+    pub name: String,
+    pub display_name: Option<String>,
+    pub proxy_tags: Vec<ProxyTag>,
+}
+
+#[derive(Deserialize)]
+pub struct ProxyTag {
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+}
+
+// Clients don't start until the import is done, so don't wait on PluralKit forever
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn fetch_members(config: &PluralkitConfig) -> Result<Vec<PluralkitMember>, reqwest::Error> {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?
+        .get(format!("{}/systems/@me/members", config.api_url.trim_end_matches('/')))
+        .header("Authorization", config.api_token.as_str())
+        .header("User-Agent", concat!("seance-rs/", env!("CARGO_PKG_VERSION")))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<PluralkitMember>>()
+        .await
+}
+
+/// Fills in display names and message patterns of configured members from their PluralKit
+/// counterparts, returning log lines describing what was merged.
+///
+/// Anything set explicitly in the config takes precedence over what PluralKit reports.
+pub fn merge_members(system: &mut System, pluralkit_members: Vec<PluralkitMember>) -> Vec<String> {
+    let mut log = Vec::new();
+    let mut unmatched = Vec::new();
+
+    for pluralkit_member in pluralkit_members {
+        let member = system.members.iter_mut().find(|member| match &member.pluralkit_id {
+            Some(pluralkit_id) => *pluralkit_id == pluralkit_member.id,
+            None => member.name.eq_ignore_ascii_case(&pluralkit_member.name),
+        });
+
+        let Some(member) = member else {
+            unmatched.push(pluralkit_member.name);
+            continue
+        };
+
+        member.pluralkit_id = Some(pluralkit_member.id);
+
+        if member.display_name.is_none() {
+            member.display_name = pluralkit_member.display_name;
+        }
+
+        if member.message_pattern.is_none() {
+            member.message_pattern = pattern_from_proxy_tags(&pluralkit_member.proxy_tags);
+
+            if member.message_pattern.is_some() {
+                log.push(format!("Imported proxy tags for {} from PluralKit", member.display_name.as_ref().unwrap_or(&member.name)));
+            }
+        }
+    }
+
+    if !unmatched.is_empty() {
+        log.push(format!("PluralKit members without a configured bot: {}", unmatched.join(", ")));
+    }
+
+    for member in system.members.iter().filter(|member| member.message_pattern.is_none()) {
+        log.push(format!("WARNING: Member {} has no message pattern", member.name));
+    }
+
+    log
+}
+
+/// Builds a single message pattern out of a list of proxy tags, with one alternative per tag.
+///
+/// Capture group names have to be unique, so the content groups are named `content`,
+/// `content1`, `content2`, etc.
+pub fn pattern_from_proxy_tags(proxy_tags: &[ProxyTag]) -> Option<Regex> {
+    let alternatives: Vec<String> = proxy_tags.iter()
+        .filter(|tag| tag.prefix.is_some() || tag.suffix.is_some())
+        .enumerate()
+        .map(|(index, tag)| {
+            let group_name = if index == 0 { "content".to_string() } else { format!("content{index}") };
+
+            format!("{}(?<{group_name}>.*){}",
+                regex::escape(tag.prefix.as_deref().unwrap_or("")),
+                regex::escape(tag.suffix.as_deref().unwrap_or("")),
+            )
+        })
+        .collect();
+
+    if alternatives.is_empty() {
+        return None
+    }
+
+    build_message_pattern(format!("^(?:{})$", alternatives.join("|"))).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const MEMBERS_RESPONSE: &str = r#"[
+        {"id": "abcde", "name": "Alice", "display_name": "Alice 🌸", "proxy_tags": [{"prefix": "a:", "suffix": null}, {"prefix": "[", "suffix": "]"}]},
+        {"id": "fghij", "name": "Bob", "display_name": null, "proxy_tags": [{"prefix": null, "suffix": "-b"}]},
+        {"id": "klmno", "name": "Carol", "display_name": null, "proxy_tags": []}
+    ]"#;
+
+    fn test_system() -> System {
+        toml::from_str(r#"
+            reference_user_id = "1"
+
+            [[members]]
+            name = "alice"
+            discord_token = "token"
+
+            [[members]]
+            name = "Robert"
+            pluralkit_id = "fghij"
+            discord_token = "token"
+
+            [[members]]
+            name = "Dave"
+            display_name = "Dave!"
+            message_pattern = "d:(?<content>.*)"
+            discord_token = "token"
+        "#).unwrap()
+    }
+
+    async fn serve_once(response_body: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response_body.len(),
+                response_body,
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+
+            String::from_utf8(request).unwrap()
+        });
+
+        (format!("http://{address}/v2"), handle)
+    }
+
+    #[tokio::test]
+    async fn fetches_members_from_api() {
+        let (api_url, server) = serve_once(MEMBERS_RESPONSE).await;
+
+        let config = PluralkitConfig {
+            message_pattern: build_message_pattern("pk;.*".to_string()).unwrap(),
+            api_token: "secret-token".to_string(),
+            api_url,
+        };
+
+        let members = fetch_members(&config).await.unwrap();
+        let request = server.await.unwrap();
+
+        assert!(request.starts_with("GET /v2/systems/@me/members HTTP/1.1\r\n"));
+        assert!(request.to_lowercase().contains("authorization: secret-token\r\n"));
+
+        assert_eq!(members.len(), 3);
+        assert_eq!(members[0].id, "abcde");
+        assert_eq!(members[0].proxy_tags.len(), 2);
+        assert_eq!(members[1].proxy_tags[0].suffix.as_deref(), Some("-b"));
+    }
+
+    #[test]
+    fn merges_members_by_name_and_id() {
+        let mut system = test_system();
+        let pluralkit_members: Vec<PluralkitMember> = serde_json::from_str(MEMBERS_RESPONSE).unwrap();
+
+        let log = merge_members(&mut system, pluralkit_members);
+
+        let alice = &system.members[0];
+        assert_eq!(alice.pluralkit_id.as_deref(), Some("abcde"));
+        assert_eq!(alice.display_name.as_deref(), Some("Alice 🌸"));
+
+        let bob = &system.members[1];
+        assert_eq!(bob.pluralkit_id.as_deref(), Some("fghij"));
+        assert!(bob.message_pattern.as_ref().unwrap().is_match("hello-b"));
+
+        // Config values win over PluralKit
+        let dave = &system.members[2];
+        assert_eq!(dave.display_name.as_deref(), Some("Dave!"));
+        assert!(dave.pluralkit_id.is_none());
+
+        assert!(log.contains(&"PluralKit members without a configured bot: Carol".to_string()));
+    }
+
+    #[test]
+    fn generates_pattern_per_proxy_tag() {
+        let tags = vec![
+            ProxyTag { prefix: Some("a:".to_string()), suffix: None },
+            ProxyTag { prefix: Some("[".to_string()), suffix: Some("]".to_string()) },
+            ProxyTag { prefix: None, suffix: None },
+        ];
+
+        let pattern = pattern_from_proxy_tags(&tags).unwrap();
+
+        let captures = pattern.captures("a: hi there").unwrap();
+        assert_eq!(captures.name("content").unwrap().as_str(), " hi there");
+
+        let captures = pattern.captures("[hi there]").unwrap();
+        assert_eq!(captures.name("content1").unwrap().as_str(), "hi there");
+
+        assert!(!pattern.is_match("hi there]"));
+        assert!(pattern_from_proxy_tags(&[]).is_none());
+    }
+}
@@ -0,0 +1,209 @@
+//! A `ProxyBackend` that keeps everything in memory and records each call, so the proxying
+//! logic in `Manager` can be tested without talking to Discord.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde_json::json;
+use twilight_model::id::Id;
+use twilight_model::util::Timestamp;
+
+use super::backend::ProxyBackend;
+use super::types::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackendCall {
+    SetStatus(MemberId, Status),
+    SetNick(MemberId, ServerId, String),
+    ResendMessage(MemberId, MessageId),
+    TriggerTyping(MemberId, ChannelId),
+    SendMessage(MemberId, ChannelId, String),
+    SendDirectMessage(MemberId, UserId, String),
+    DuplicateMessage { member_id: MemberId, original_id: MessageId, proxied_id: MessageId, content: String },
+    EditMessage(MemberId, MessageId, String),
+    DeleteMessage(MemberId, MessageId),
+    React(MemberId, MessageId, Emoji),
+    Unreact(MemberId, MessageId, Emoji),
+    RemoveReaction(MemberId, MessageId, Emoji, UserId),
+}
+
+#[derive(Debug)]
+pub struct RecordedFailure;
+
+impl fmt::Display for RecordedFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request failed")
+    }
+}
+
+/// Call log shared by every member's backend in a system
+#[derive(Default)]
+pub struct Recording {
+    calls: Mutex<Vec<BackendCall>>,
+    sent: Mutex<Vec<TwiMessage>>,
+    next_id: AtomicU64,
+    pub fail_deletes: AtomicBool,
+    pub fail_reactions: AtomicBool,
+}
+
+impl Recording {
+    pub fn calls(&self) -> Vec<BackendCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Messages sent as members, for handing back to the system the way the gateway would
+    pub fn sent(&self) -> Vec<TwiMessage> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    fn record(&self, call: BackendCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+
+    fn next_id<T>(&self) -> Id<T> {
+        // Well clear of the ids tests hand out themselves
+        Id::new(1_000_000 + self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+pub struct RecordingBackend {
+    member_id: MemberId,
+    user_id: UserId,
+    recording: Arc<Recording>,
+}
+
+impl RecordingBackend {
+    pub fn new(member_id: MemberId, user_id: UserId, recording: &Arc<Recording>) -> Self {
+        Self { member_id, user_id, recording: recording.clone() }
+    }
+
+    fn send_as_member(&self, mut message: TwiMessage, content: &str) -> TwiMessage {
+        message.id = self.recording.next_id();
+        message.author.id = self.user_id;
+        message.author.bot = true;
+        message.content = content.to_string();
+        message.edited_timestamp = None;
+
+        self.recording.sent.lock().unwrap().push(message.clone());
+        message
+    }
+
+    /// A new message from the member rather than a copy of the user's
+    fn send_new(&self, channel_id: ChannelId, content: &str) -> TwiMessage {
+        let message: TwiMessage = serde_json::from_value(json!({
+            "id": "1",
+            "channel_id": channel_id.to_string(),
+            "author": { "id": self.user_id.to_string(), "username": "member", "discriminator": "0001", "avatar": null },
+            "content": "",
+            "timestamp": Timestamp::from_secs(1_700_000_000).unwrap().iso_8601().to_string(),
+            "edited_timestamp": null,
+            "tts": false,
+            "mention_everyone": false,
+            "mentions": [],
+            "mention_roles": [],
+            "attachments": [],
+            "embeds": [],
+            "pinned": false,
+            "type": 0,
+        })).unwrap();
+
+        self.send_as_member(message, content)
+    }
+
+    fn edit_sent(&self, message_id: MessageId, new_content: String) -> Result<FullMessage, RecordedFailure> {
+        let mut sent = self.recording.sent.lock().unwrap();
+        let message = sent.iter_mut().find(|message| message.id == message_id).ok_or(RecordedFailure)?;
+
+        message.content = new_content;
+        message.edited_timestamp = Some(message.timestamp);
+        Ok(message.clone())
+    }
+}
+
+impl ProxyBackend for RecordingBackend {
+    type Error = RecordedFailure;
+
+    async fn set_status(&self, status: Status) {
+        self.recording.record(BackendCall::SetStatus(self.member_id, status));
+    }
+
+    async fn set_nick(&self, server_id: ServerId, nick: String) {
+        self.recording.record(BackendCall::SetNick(self.member_id, server_id, nick));
+    }
+
+    async fn resend_message(&self, message_id: MessageId, _channel_id: ChannelId) {
+        self.recording.record(BackendCall::ResendMessage(self.member_id, message_id));
+    }
+
+    async fn fetch_recent_channel_messages(&self, channel_id: ChannelId) -> Result<Vec<FullMessage>, RecordedFailure> {
+        Ok(self.recording.sent().into_iter().filter(|message| message.channel_id == channel_id).collect())
+    }
+
+    async fn trigger_typing(&self, channel_id: ChannelId) -> Result<(), RecordedFailure> {
+        self.recording.record(BackendCall::TriggerTyping(self.member_id, channel_id));
+        Ok(())
+    }
+
+    async fn send_message(&self, channel_id: ChannelId, content: &str) -> Result<FullMessage, RecordedFailure> {
+        self.recording.record(BackendCall::SendMessage(self.member_id, channel_id, content.to_string()));
+        Ok(self.send_new(channel_id, content))
+    }
+
+    async fn send_direct_message(&self, user_id: UserId, content: &str) -> Result<FullMessage, RecordedFailure> {
+        self.recording.record(BackendCall::SendDirectMessage(self.member_id, user_id, content.to_string()));
+
+        // Direct messages go to a channel of their own
+        let channel_id = self.recording.next_id();
+        Ok(self.send_new(channel_id, content))
+    }
+
+    async fn duplicate_message(&self, message: &TwiMessage, content: &str) -> Result<TwiMessage, RecordedFailure> {
+        let proxied = self.send_as_member(message.clone(), content);
+
+        self.recording.record(BackendCall::DuplicateMessage {
+            member_id: self.member_id,
+            original_id: message.id,
+            proxied_id: proxied.id,
+            content: content.to_string(),
+        });
+
+        Ok(proxied)
+    }
+
+    async fn edit_message(&self, _channel_id: ChannelId, message_id: MessageId, new_content: String) -> Result<FullMessage, RecordedFailure> {
+        self.recording.record(BackendCall::EditMessage(self.member_id, message_id, new_content.clone()));
+        self.edit_sent(message_id, new_content)
+    }
+
+    async fn delete_message(&self, _channel_id: ChannelId, message_id: MessageId) -> Result<(), RecordedFailure> {
+        self.recording.record(BackendCall::DeleteMessage(self.member_id, message_id));
+
+        if self.recording.fail_deletes.load(Ordering::Relaxed) {
+            return Err(RecordedFailure)
+        }
+
+        self.recording.sent.lock().unwrap().retain(|message| message.id != message_id);
+        Ok(())
+    }
+
+    async fn react_message(&self, _channel_id: ChannelId, message_id: MessageId, emoji: &Emoji) -> Result<(), RecordedFailure> {
+        self.recording.record(BackendCall::React(self.member_id, message_id, emoji.clone()));
+
+        if self.recording.fail_reactions.load(Ordering::Relaxed) {
+            return Err(RecordedFailure)
+        }
+
+        Ok(())
+    }
+
+    async fn unreact_message(&self, _channel_id: ChannelId, message_id: MessageId, emoji: &Emoji) -> Result<(), RecordedFailure> {
+        self.recording.record(BackendCall::Unreact(self.member_id, message_id, emoji.clone()));
+        Ok(())
+    }
+
+    async fn remove_reaction(&self, _channel_id: ChannelId, message_id: MessageId, emoji: &Emoji, user_id: UserId) -> Result<(), RecordedFailure> {
+        self.recording.record(BackendCall::RemoveReaction(self.member_id, message_id, emoji.clone(), user_id));
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::MemberName;
+use super::{LatchScope, Timestamp};
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedLatch {
+    pub scope: LatchScope,
+    pub member: MemberName,
+    pub timestamp: Timestamp,
+}
+
+pub fn load_latches(path: &Path) -> io::Result<Vec<SavedLatch>> {
+    if !path.exists() {
+        return Ok(Vec::new())
+    }
+
+    let contents = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(contents.as_str())?)
+}
+
+pub fn save_latches(path: &Path, latches: &[SavedLatch]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Write to a temporary file first so a crash can't leave half a state file behind
+    let temporary_path = path.with_extension("tmp");
+    fs::write(&temporary_path, serde_json::to_string(latches)?)?;
+    fs::rename(temporary_path, path)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedSwitch {
+    pub timestamp: Timestamp,
+    pub members: Vec<MemberName>,
+}
+
+pub fn load_switches(path: &Path) -> io::Result<Vec<SavedSwitch>> {
+    if !path.exists() {
+        return Ok(Vec::new())
+    }
+
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+pub fn append_switch(path: &Path, switch: &SavedSwitch) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut line = serde_json::to_string(switch)?;
+    line.push('\n');
+
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(line.as_bytes())
+}
@@ -0,0 +1,860 @@
+use std::collections::HashSet;
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc};
+
+use serde_json::json;
+use tokio::sync::mpsc::channel;
+use twilight_model::id::Id;
+
+use super::fake_discord::{FakeDiscord, CHANNEL, OTHER_USER, REFERENCE_USER, SERVER, THREAD};
+use super::recording::{BackendCall, Recording, RecordingBackend};
+use super::*;
+
+/// Removes the system's data directory once the test is done with it
+struct DataDirectory(PathBuf);
+
+impl DataDirectory {
+    fn new(test_name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("seance-test-{}-{test_name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        Self(path)
+    }
+
+    fn system_config(&self, extra: &str) -> crate::config::System {
+        toml::from_str(&format!(r#"
+            reference_user_id = "{REFERENCE_USER}"
+            data_directory = "{}"
+            {extra}
+
+            [[members]]
+            name = "Alice"
+            message_pattern = "a:(?<content>.*)"
+            discord_token = "alice-token"
+
+            [[members]]
+            name = "Bob"
+            message_pattern = "b:(?<content>.*)"
+            discord_token = "bob-token"
+        "#, self.0.display())).unwrap()
+    }
+}
+
+impl Drop for DataDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A system talking to a fake Discord over HTTP and websockets
+struct TestSystem {
+    manager: Manager,
+    _data_directory: DataDirectory,
+    ui_receiver: Option<mpsc::Receiver<(String, SystemUiEvent)>>,
+    system_channel: Option<(Sender<SystemEvent>, Receiver<SystemEvent>)>,
+}
+
+impl TestSystem {
+    fn new(fake: &FakeDiscord, test_name: &str) -> Self {
+        Self::with_config(fake, test_name, "")
+    }
+
+    fn with_webhooks(fake: &FakeDiscord, test_name: &str) -> Self {
+        Self::with_config(fake, test_name, r#"webhook = { listener_token = "listener-token" }"#)
+    }
+
+    fn with_config(fake: &FakeDiscord, test_name: &str, extra: &str) -> Self {
+        let data_directory = DataDirectory::new(test_name);
+        let config = data_directory.system_config(&format!(
+            r#"discord = {{ api_url = "{}", gateway_url = "{}" }}
+            {extra}"#,
+            fake.api_url, fake.gateway_url,
+        ));
+
+        let (ui_sender, ui_receiver) = mpsc::channel();
+
+        Self {
+            manager: Manager::new("test".to_string(), config, ui_sender),
+            _data_directory: data_directory,
+            ui_receiver: Some(ui_receiver),
+            system_channel: Some(channel::<SystemEvent>(100)),
+        }
+    }
+
+    /// For scenarios to send the system events of their own while it runs
+    fn system_sender(&self) -> Sender<SystemEvent> {
+        self.system_channel.as_ref().expect("System already ran").0.clone()
+    }
+
+    /// Hands over what the system reports to the UI, for scenarios to watch while it runs
+    fn take_ui_events(&mut self) -> mpsc::Receiver<(String, SystemUiEvent)> {
+        self.ui_receiver.take().expect("UI events already taken")
+    }
+
+    /// Runs the system until the scenario finishes
+    async fn run(&mut self, scenario: impl Future<Output = ()>) {
+        let (system_sender, system_receiver) = self.system_channel.take().expect("System already ran");
+
+        tokio::select! {
+            _ = self.manager.start_clients((system_sender, system_receiver)) => panic!("System exited during test"),
+            _ = scenario => (),
+        }
+    }
+}
+
+async fn proxy(fake: &FakeDiscord, content: &str, proxied_content: &str) -> TwiMessage {
+    fake.wait_for_shards(2).await;
+
+    let original = fake.user_sends(content);
+    let proxied = fake.wait_for_message("the proxied message", |message| {
+        message.author.bot && message.content == proxied_content
+    }).await;
+
+    fake.wait_for_deletion(original).await;
+    proxied
+}
+
+#[tokio::test]
+async fn proxies_message_as_member() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::new(&fake, "proxy");
+
+    system.run(async {
+        let proxied = proxy(&fake, "a:hello there", "hello there").await;
+        assert_eq!(proxied.author.id, fake.bot_user("alice-token"));
+    }).await;
+}
+
+#[tokio::test]
+async fn edits_proxied_message() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::new(&fake, "edit");
+
+    system.run(async {
+        let proxied = proxy(&fake, "a:helo", "helo").await;
+
+        let command = fake.user_sends("!edit hello");
+        fake.wait_for_deletion(command).await;
+
+        let edited = fake.message(proxied.id).unwrap();
+        assert_eq!(edited.content, "hello");
+        assert!(edited.edited_timestamp.is_some());
+        assert!(!fake.is_deleted(proxied.id));
+    }).await;
+}
+
+#[tokio::test]
+async fn survives_editing_a_message_whose_proxy_is_gone() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::new(&fake, "edit-deleted");
+
+    system.run(async {
+        fake.wait_for_shards(2).await;
+
+        let original = fake.user_sends("a:helo");
+        let proxied = fake.wait_for_message("the proxied message", |message| message.author.bot && message.content == "helo").await;
+        fake.wait_for_deletion(original).await;
+
+        fake.delete(proxied.id);
+        fake.user_edits(original, "a:hello");
+
+        // Still proxying afterwards
+        proxy(&fake, "b:still here", "still here").await;
+    }).await;
+}
+
+#[tokio::test]
+async fn deletes_proxied_message() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::new(&fake, "delete");
+
+    system.run(async {
+        let proxied = proxy(&fake, "b:oops", "oops").await;
+
+        let command = fake.user_sends("!delete");
+        fake.wait_for_deletion(command).await;
+        fake.wait_for_deletion(proxied.id).await;
+    }).await;
+}
+
+#[tokio::test]
+async fn reproxies_as_another_member() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::new(&fake, "reproxy");
+
+    system.run(async {
+        let proxied = proxy(&fake, "a:who said this", "who said this").await;
+        assert_eq!(proxied.author.id, fake.bot_user("alice-token"));
+
+        let command = fake.user_sends("!reproxy bob");
+        let reproxied = fake.wait_for_message("the reproxied message", |message| {
+            message.id != proxied.id && message.content == "who said this"
+        }).await;
+
+        assert_eq!(reproxied.author.id, fake.bot_user("bob-token"));
+        fake.wait_for_deletion(proxied.id).await;
+        fake.wait_for_deletion(command).await;
+    }).await;
+}
+
+#[tokio::test]
+async fn reacts_to_proxied_message() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::new(&fake, "react");
+
+    system.run(async {
+        let proxied = proxy(&fake, "a:nice", "nice").await;
+
+        let command = fake.user_sends("+👍");
+        fake.wait_for_deletion(command).await;
+
+        // Emoji arrive percent-encoded in the route
+        assert_eq!(fake.reactions(proxied.id), vec!["%F0%9F%91%8D".to_string()]);
+    }).await;
+}
+
+#[tokio::test]
+async fn proxies_through_webhooks() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::with_webhooks(&fake, "webhook");
+
+    system.run(async {
+        fake.wait_for_shards(1).await;
+
+        let original = fake.user_sends("b:hello there");
+        let proxied = fake.wait_for_message("the webhook message", |message| message.webhook_id.is_some()).await;
+        fake.wait_for_deletion(original).await;
+
+        assert_eq!((proxied.author.name.as_str(), proxied.content.as_str()), ("Bob", "hello there"));
+
+        let command = fake.user_sends("!edit hi there");
+        fake.wait_for_deletion(command).await;
+        assert_eq!(fake.message(proxied.id).unwrap().content, "hi there");
+    }).await;
+}
+
+#[tokio::test]
+async fn keeps_the_reply_link_when_editing_a_webhook_reply() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::with_webhooks(&fake, "webhook-reply-edit");
+
+    system.run(async {
+        fake.wait_for_shards(1).await;
+
+        let replied_to = fake.user_sends("not proxied");
+        fake.user_replies(replied_to, "b:hello there");
+        let proxied = fake.wait_for_message("the webhook reply", |message| message.webhook_id.is_some()).await;
+
+        let reply_line = proxied.content.lines().next().unwrap().to_string();
+        assert!(reply_line.starts_with("-# ↪ ") && reply_line.ends_with(&replied_to.to_string()));
+
+        let command = fake.user_sends("!edit hi there");
+        fake.wait_for_deletion(command).await;
+        assert_eq!(fake.message(proxied.id).unwrap().content, format!("{reply_line}\nhi there"));
+    }).await;
+}
+
+#[tokio::test]
+async fn proxies_through_the_parent_webhook_in_threads() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::with_webhooks(&fake, "webhook-thread");
+
+    system.run(async {
+        fake.wait_for_shards(1).await;
+
+        let original = fake.user_sends_in(Id::new(THREAD), "b:hello there");
+        let proxied = fake.wait_for_message("the webhook message", |message| message.webhook_id.is_some()).await;
+        fake.wait_for_deletion(original).await;
+
+        assert_eq!(proxied.channel_id, Id::new(THREAD));
+        assert_eq!(proxied.content, "hello there");
+
+        let command = fake.user_sends_in(Id::new(THREAD), "!edit hi there");
+        fake.wait_for_deletion(command).await;
+        assert_eq!(fake.message(proxied.id).unwrap().content, "hi there");
+    }).await;
+}
+
+#[tokio::test]
+async fn keeps_webhooks_when_the_listener_reconnects() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::with_webhooks(&fake, "webhook-reconnect");
+    let system_sender = system.system_sender();
+
+    system.run(async {
+        fake.wait_for_shards(1).await;
+
+        fake.user_sends("a:hello there");
+        fake.wait_for_message("the first webhook message", |message| message.content == "hello there").await;
+
+        system_sender.send(SystemEvent::ReconnectGateway(0, None)).await.unwrap();
+        fake.wait_for("the listener to identify again", || (fake.identify_count("listener-token") == 2).then_some(())).await;
+
+        fake.user_sends("b:hi again");
+        let proxied = fake.wait_for_message("the second webhook message", |message| message.content == "hi again").await;
+
+        assert_eq!(proxied.author.name, "Bob");
+        assert_eq!(fake.request_count(&format!("GET channels/{CHANNEL}/webhooks")), 1);
+    }).await;
+}
+
+#[tokio::test]
+async fn clears_latches_of_every_scope() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::with_config(&fake, "clear-all-latches",
+        r#"autoproxy = { mode = "latch", scope = "server", timeout_seconds = 3600, presence_indicator = false }"#);
+    let system_sender = system.system_sender();
+    let ui_events = system.take_ui_events();
+
+    system.run(async {
+        proxy(&fake, "b:hi", "hi").await;
+        system_sender.send(SystemEvent::ClearAllLatches).await.unwrap();
+
+        let (scope, member) = fake.wait_for("the server latch to be cleared", || {
+            ui_events.try_iter().find_map(|(_, event)| match event {
+                SystemUiEvent::ScopeAutoproxy(scope, member) if member.is_none() => Some((scope, member)),
+                _ => None,
+            })
+        }).await;
+
+        assert_eq!((scope, member), (format!("server {SERVER}"), None));
+    }).await;
+}
+
+#[tokio::test]
+async fn proxies_with_one_listener_for_the_system() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::with_config(&fake, "listener", r#"gateway_listener = "Alice""#);
+
+    system.run(async {
+        let proxied = proxy(&fake, "b:hello there", "hello there").await;
+        assert_eq!(proxied.author.id, fake.bot_user("bob-token"));
+        assert_eq!(fake.listening_shards(), 1);
+    }).await;
+}
+
+#[tokio::test]
+async fn forwards_pings_by_direct_message() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::with_config(&fake, "forward-ping", "forward_pings = true");
+
+    system.run(async {
+        fake.wait_for_shards(2).await;
+
+        let ping = fake.other_user_mentions("bob-token", "hey bob");
+        let notification = fake.wait_for_message("the forwarded ping", |message| message.content.contains("mentioned Bob")).await;
+
+        assert_eq!(notification.author.id, fake.bot_user("bob-token"));
+        assert_ne!(notification.channel_id, Id::new(CHANNEL));
+        assert!(notification.content.contains(&ping.to_string()));
+    }).await;
+}
+
+#[tokio::test]
+async fn survives_a_broken_direct_message_channel() {
+    let fake = FakeDiscord::start().await;
+    fake.break_direct_messages();
+
+    let mut system = TestSystem::with_config(&fake, "forward-ping-broken", "forward_pings = true");
+    let ui_events = system.take_ui_events();
+
+    system.run(async {
+        fake.wait_for_shards(2).await;
+        fake.other_user_mentions("bob-token", "hey bob");
+
+        fake.wait_for("the failed forward to be logged", || {
+            ui_events.try_iter()
+                .any(|(_, event)| matches!(event, SystemUiEvent::LogLine(line) if line.starts_with("Could not forward ping for Bob")))
+                .then_some(())
+        }).await;
+
+        proxy(&fake, "a:still here", "still here").await;
+        assert_eq!(fake.identify_count("alice-token"), 1);
+    }).await;
+}
+
+#[tokio::test]
+async fn forwards_pings_through_the_system_listener() {
+    let fake = FakeDiscord::start().await;
+    let mut system = TestSystem::with_config(&fake, "forward-ping-listener", r#"
+        gateway_listener = "Alice"
+        forward_pings = true
+    "#);
+    let ui_events = system.take_ui_events();
+
+    system.run(async {
+        // Bob's user has to be known before a mention of it can be told apart
+        fake.wait_for("Bob to connect", || {
+            ui_events.try_iter()
+                .any(|(_, event)| matches!(event, SystemUiEvent::GatewayConnect(member) if member == "Bob"))
+                .then_some(())
+        }).await;
+
+        fake.other_user_mentions("bob-token", "hey bob");
+        let notification = fake.wait_for_message("the forwarded ping", |message| message.content.contains("mentioned Bob")).await;
+
+        assert_eq!(notification.author.id, fake.bot_user("bob-token"));
+        assert_eq!(fake.listening_shards(), 1);
+    }).await;
+}
+
+#[tokio::test]
+async fn gives_up_on_rejected_tokens() {
+    let fake = FakeDiscord::start().await;
+    fake.reject_token("bob-token");
+
+    let mut system = TestSystem::new(&fake, "rejected");
+    let ui_events = system.take_ui_events();
+
+    system.run(async {
+        fake.wait_for("Bob to be marked failed", || {
+            ui_events.try_iter()
+                .any(|(_, event)| matches!(event, SystemUiEvent::GatewayFailed(member) if member == "Bob"))
+                .then_some(())
+        }).await;
+
+        // Alice still proxies, and Bob's token isn't tried again
+        fake.user_sends("a:still here");
+        let proxied = fake.wait_for_message("Alice's message", |message| message.author.bot && message.content == "still here").await;
+
+        assert_eq!(proxied.author.id, fake.bot_user("alice-token"));
+        assert_eq!(fake.identify_count("bob-token"), 1);
+    }).await;
+}
+
+#[test]
+fn gateway_retries_back_off_up_to_a_cap() {
+    for (retries, backoff_secs) in [(1, 2), (2, 4), (5, 32), (8, 256), (20, 300), (u32::MAX, 300)] {
+        let backoff = Duration::from_secs(backoff_secs);
+
+        for _ in 0..100 {
+            let delay = reconnect_delay(retries);
+            assert!(delay >= backoff && delay <= backoff * 5 / 4, "retry {retries} waited {delay:?}");
+        }
+    }
+
+    let delays: HashSet<Duration> = (0..100).map(|_| reconnect_delay(20)).collect();
+    assert!(delays.iter().all(|delay| *delay <= GATEWAY_RETRY_CAP * 5 / 4));
+    assert!(delays.len() > 1, "Retries should not all wait the same time");
+}
+
+/// A system whose members all go through the recording backend, fed messages directly
+struct RecordedSystem {
+    manager: Manager<RecordingBackend>,
+    recording: Arc<Recording>,
+    next_id: u64,
+    _data_directory: DataDirectory,
+    ui_receiver: mpsc::Receiver<(String, SystemUiEvent)>,
+}
+
+impl RecordedSystem {
+    fn new(test_name: &str, extra: &str) -> Self {
+        let data_directory = DataDirectory::new(test_name);
+        let (ui_sender, ui_receiver) = mpsc::channel();
+        let mut manager = Manager::new("test".to_string(), data_directory.system_config(extra), ui_sender);
+
+        // Stand in for each member's gateway connecting
+        let recording = Arc::new(Recording::default());
+        for member_id in 0..manager.config.members.len() {
+            let user_id = Id::new(200 + member_id as u64);
+            manager.config.members[member_id].user_id = Some(user_id);
+            manager.bots.insert(member_id, RecordingBackend::new(member_id, user_id, &recording));
+        }
+
+        Self {
+            manager,
+            recording,
+            next_id: 0,
+            _data_directory: data_directory,
+            ui_receiver,
+        }
+    }
+
+    async fn user_sends(&mut self, content: &str) -> MessageId {
+        self.user_sends_command(content).await.0
+    }
+
+    /// Sends a message, also handing back what the system thread should do after it
+    async fn user_sends_command(&mut self, content: &str) -> (MessageId, Option<SystemThreadCommand>) {
+        let message = self.next_message(REFERENCE_USER, content);
+        let (message_id, timestamp) = (message.id, message.timestamp);
+
+        (message_id, self.manager.handle_message(message, timestamp, 0).await)
+    }
+
+    /// Someone mentions a member, as their gateway would pass it on
+    async fn pings(&mut self, author: u64, member_id: MemberId, content: &str) {
+        let message = self.next_message(author, content);
+        self.manager.forward_ping(member_id, message).await;
+    }
+
+    fn next_message(&mut self, author: u64, content: &str) -> TwiMessage {
+        self.next_id += 1;
+        let timestamp = Timestamp::from_secs(1_700_000_000 + self.next_id as i64).unwrap();
+
+        serde_json::from_value(json!({
+            "id": self.next_id.to_string(),
+            "channel_id": CHANNEL.to_string(),
+            "guild_id": SERVER.to_string(),
+            "author": { "id": author.to_string(), "username": "user", "discriminator": "0001", "avatar": null },
+            "content": content,
+            "timestamp": timestamp.iso_8601().to_string(),
+            "edited_timestamp": null,
+            "tts": false,
+            "mention_everyone": false,
+            "mentions": [],
+            "mention_roles": [],
+            "attachments": [],
+            "embeds": [],
+            "pinned": false,
+            "type": 0,
+        })).unwrap()
+    }
+
+    /// Every member's gateway reports the user typing, the system only hears about it once here
+    async fn user_types_in(&mut self, channel_id: u64) {
+        self.manager.mirror_typing(Some(Id::new(SERVER)), Id::new(channel_id)).await;
+    }
+
+    fn log_lines(&self) -> Vec<String> {
+        self.ui_receiver.try_iter().filter_map(|(_, event)| match event {
+            SystemUiEvent::LogLine(line) => Some(line),
+            _ => None,
+        }).collect()
+    }
+
+    async fn user_reacts(&mut self, message_id: MessageId, emoji: &str) {
+        let reaction: GatewayReaction = serde_json::from_value(json!({
+            "channel_id": CHANNEL.to_string(),
+            "guild_id": SERVER.to_string(),
+            "message_id": message_id.to_string(),
+            "user_id": REFERENCE_USER.to_string(),
+            "emoji": { "name": emoji },
+            "burst": false,
+            "burst_colors": [],
+            "type": 0,
+        })).unwrap();
+
+        self.manager.handle_reaction(reaction).await;
+    }
+
+    fn proxied_messages(&self) -> Vec<(MemberId, MessageId, String)> {
+        self.recording.calls().into_iter().filter_map(|call| match call {
+            BackendCall::DuplicateMessage { member_id, proxied_id, content, .. } => Some((member_id, proxied_id, content)),
+            _ => None,
+        }).collect()
+    }
+}
+
+#[tokio::test]
+async fn proxy_copies_message_then_deletes_original() {
+    let mut system = RecordedSystem::new("recorded-proxy", "");
+    let original = system.user_sends("a:hello").await;
+
+    let calls = system.recording.calls();
+    let [BackendCall::DuplicateMessage { member_id: 0, original_id, proxied_id, content }, BackendCall::DeleteMessage(0, deleted), ..] = calls.as_slice() else {
+        panic!("Unexpected calls {calls:?}")
+    };
+
+    assert_eq!((*original_id, content.as_str(), *deleted), (original, "hello", original));
+
+    let record = system.manager.message_store.by_original_id(original).unwrap();
+    assert_eq!((record.proxied_id, record.member.as_str()), (*proxied_id, "Alice"));
+}
+
+#[tokio::test]
+async fn proxy_is_undone_when_original_cannot_be_deleted() {
+    let mut system = RecordedSystem::new("recorded-undo", "");
+    system.recording.fail_deletes.store(true, Ordering::Relaxed);
+
+    let original = system.user_sends("a:hello").await;
+    let (_, proxied_id, _) = system.proxied_messages()[0].clone();
+
+    assert_eq!(system.recording.calls()[1..3], [
+        BackendCall::DeleteMessage(0, original),
+        BackendCall::DeleteMessage(0, proxied_id),
+    ]);
+    assert!(system.manager.message_store.by_original_id(original).is_none());
+}
+
+#[tokio::test]
+async fn leaves_untagged_messages_alone() {
+    let mut system = RecordedSystem::new("recorded-untagged", "");
+    system.user_sends("just talking").await;
+
+    assert_eq!(system.recording.calls(), []);
+}
+
+#[tokio::test]
+async fn edit_command_edits_last_proxied_message() {
+    let mut system = RecordedSystem::new("recorded-edit", "");
+    system.user_sends("a:helo").await;
+    let command = system.user_sends("!edit hello").await;
+
+    let (_, proxied_id, _) = system.proxied_messages()[0].clone();
+    assert!(system.recording.calls().ends_with(&[
+        BackendCall::EditMessage(0, proxied_id, "hello".to_string()),
+        BackendCall::DeleteMessage(0, command),
+    ]));
+}
+
+#[tokio::test]
+async fn correction_that_changes_nothing_only_cleans_up() {
+    let mut system = RecordedSystem::new("recorded-noop-correction", "");
+    system.user_sends("a:hello").await;
+    let command = system.user_sends("*hello").await;
+
+    let calls = system.recording.calls();
+    assert_eq!(calls.last(), Some(&BackendCall::DeleteMessage(0, command)));
+    assert!(!calls.iter().any(|call| matches!(call, BackendCall::EditMessage(..))));
+}
+
+#[tokio::test]
+async fn reaction_command_reacts_as_member() {
+    let mut system = RecordedSystem::new("recorded-react", "");
+    system.user_sends("b:nice").await;
+    let command = system.user_sends("+👍").await;
+
+    let (_, proxied_id, _) = system.proxied_messages()[0].clone();
+    assert!(system.recording.calls().ends_with(&[
+        BackendCall::React(1, proxied_id, Emoji::Unicode("👍".to_string())),
+        BackendCall::DeleteMessage(1, command),
+    ]));
+}
+
+const LATCH_CONFIG: &str = r#"autoproxy = { mode = "latch", scope = "global", timeout_seconds = 3600, presence_indicator = true }"#;
+
+#[tokio::test]
+async fn mirrors_reactions_as_latched_member() {
+    let mut system = RecordedSystem::new("recorded-mirror", LATCH_CONFIG);
+    system.user_sends("b:hi").await;
+    system.user_reacts(Id::new(42), "👍").await;
+
+    let thumbs_up = Emoji::Unicode("👍".to_string());
+    assert!(system.recording.calls().ends_with(&[
+        BackendCall::React(1, Id::new(42), thumbs_up.clone()),
+        BackendCall::RemoveReaction(1, Id::new(42), thumbs_up, Id::new(REFERENCE_USER)),
+    ]));
+}
+
+#[tokio::test]
+async fn keeps_user_reaction_when_member_cannot_react() {
+    let mut system = RecordedSystem::new("recorded-mirror-fail", LATCH_CONFIG);
+    system.recording.fail_reactions.store(true, Ordering::Relaxed);
+
+    system.user_sends("b:hi").await;
+    system.user_reacts(Id::new(42), "👍").await;
+
+    let calls = system.recording.calls();
+    assert_eq!(calls.last(), Some(&BackendCall::React(1, Id::new(42), Emoji::Unicode("👍".to_string()))));
+    assert!(!calls.iter().any(|call| matches!(call, BackendCall::RemoveReaction(..))));
+}
+
+#[tokio::test]
+async fn latch_autoproxies_following_messages() {
+    let mut system = RecordedSystem::new("recorded-latch", LATCH_CONFIG);
+
+    system.user_sends("b:hi").await;
+    system.user_sends("still me").await;
+
+    let proxied: Vec<_> = system.proxied_messages().into_iter()
+        .map(|(member_id, _, content)| (member_id, content))
+        .collect();
+    assert_eq!(proxied, vec![(1, "hi".to_string()), (1, "still me".to_string())]);
+
+    let calls = system.recording.calls();
+    assert!(calls.contains(&BackendCall::SetStatus(1, Status::Online)));
+    assert!(calls.contains(&BackendCall::SetStatus(0, Status::Invisible)));
+}
+
+#[tokio::test]
+async fn switch_command_sets_fronters() {
+    let mut system = RecordedSystem::new("recorded-switch", "");
+    let command = system.user_sends("!switch bob alice").await;
+
+    assert_eq!(system.manager.fronters, vec![1, 0]);
+    assert_eq!(system.manager.switch_history.last().unwrap().members, vec!["Bob".to_string(), "Alice".to_string()]);
+    assert!(system.recording.calls().ends_with(&[BackendCall::DeleteMessage(1, command)]));
+}
+
+#[tokio::test]
+async fn front_command_answers_as_fronter() {
+    let mut system = RecordedSystem::new("recorded-front", "");
+    system.user_sends("!front").await;
+    system.user_sends("!switch bob").await;
+    let command = system.user_sends("!front").await;
+
+    let calls = system.recording.calls();
+    assert!(calls.contains(&BackendCall::SendMessage(0, Id::new(CHANNEL), "Nobody is fronting".to_string())));
+    assert!(calls.ends_with(&[
+        BackendCall::SendMessage(1, Id::new(CHANNEL), "Currently fronting: Bob (since <t:1700000002:R>)".to_string()),
+        BackendCall::DeleteMessage(1, command),
+    ]));
+}
+
+#[tokio::test]
+async fn fronthistory_command_lists_latest_switch_first() {
+    let mut system = RecordedSystem::new("recorded-fronthistory", "");
+    system.user_sends("!fronthistory").await;
+    system.user_sends("!switch alice").await;
+    system.user_sends("!switch").await;
+    system.user_sends("!fronthistory").await;
+
+    let responses: Vec<String> = system.recording.calls().into_iter().filter_map(|call| match call {
+        BackendCall::SendMessage(0, _, content) => Some(content),
+        _ => None,
+    }).collect();
+
+    assert_eq!(responses, vec![
+        "No switches recorded".to_string(),
+        "<t:1700000003:f>: (switched out)\n<t:1700000002:f>: Alice".to_string(),
+    ]);
+}
+
+#[tokio::test]
+async fn commands_are_ignored_when_the_fronter_has_no_client() {
+    let mut system = RecordedSystem::new("recorded-front-missing", "");
+    system.user_sends("!switch bob").await;
+    system.manager.bots.remove(&1);
+    let calls_before = system.recording.calls().len();
+
+    system.user_sends("!front").await;
+    system.user_sends("!fronthistory").await;
+
+    let calls = system.recording.calls();
+    assert!(!calls[calls_before..].iter().any(|call| matches!(call, BackendCall::SendMessage(..) | BackendCall::DeleteMessage(..))));
+}
+
+#[tokio::test]
+async fn exit_command_logs_why_before_shutting_down() {
+    let mut system = RecordedSystem::new("recorded-exit", LATCH_CONFIG);
+    system.user_sends("b:hi").await;
+
+    // Latched to a member whose client is gone
+    system.manager.bots.remove(&1);
+    let (_, command) = system.user_sends_command("!exit").await;
+
+    assert!(matches!(command, Some(SystemThreadCommand::ShutdownAll)));
+    assert!(system.log_lines().iter().any(|line| line.contains("!exit was sent")));
+}
+
+#[tokio::test]
+async fn fronthistory_command_fits_in_one_message() {
+    let mut system = RecordedSystem::new("recorded-fronthistory-long", "");
+    system.manager.config.members[0].display_name = Some("A".repeat(300));
+
+    for _ in 0..10 {
+        system.user_sends("!switch alice").await;
+    }
+    system.user_sends("!fronthistory").await;
+
+    let Some(BackendCall::SendMessage(_, _, response)) = system.recording.calls().into_iter()
+        .filter(|call| matches!(call, BackendCall::SendMessage(..)))
+        .last() else {
+        panic!("No response sent")
+    };
+
+    assert!(response.chars().count() <= MAX_CONTENT_LENGTH);
+    assert_eq!(response.lines().count(), 6);
+    assert!(response.starts_with("<t:1700000010:f>"));
+}
+
+#[tokio::test]
+async fn pluralkit_switch_drops_our_latch() {
+    let mut system = RecordedSystem::new("recorded-pluralkit", &format!(r#"
+        {LATCH_CONFIG}
+        pluralkit = {{ message_pattern = 'pk;switch (?<member>\w+)', api_token = "token" }}
+    "#));
+
+    system.user_sends("b:hi").await;
+    system.user_sends("pk;switch alice").await;
+    system.user_sends("for pluralkit to proxy").await;
+
+    assert!(system.manager.latch_state.is_empty());
+    assert_eq!(system.proxied_messages().len(), 1);
+}
+
+#[tokio::test]
+async fn clearing_front_autoproxy_switches_out() {
+    let mut system = RecordedSystem::new("recorded-front-clear", r#"autoproxy = { mode = "front", presence_indicator = false }"#);
+    system.user_sends("!switch bob").await;
+    system.user_sends(r"\\").await;
+    system.user_sends("not proxied").await;
+
+    assert!(system.manager.fronters.is_empty());
+    assert!(system.manager.latch_state.is_empty());
+    assert!(system.manager.switch_history.last().unwrap().members.is_empty());
+    assert!(system.proxied_messages().is_empty());
+}
+
+#[tokio::test]
+async fn mirrors_typing_once_per_channel_while_it_lasts() {
+    let mut system = RecordedSystem::new("recorded-typing", LATCH_CONFIG);
+    system.user_sends("b:hi").await;
+
+    system.user_types_in(CHANNEL).await;
+    system.user_types_in(CHANNEL).await;
+    system.user_types_in(THREAD).await;
+
+    let typing = |system: &RecordedSystem| system.recording.calls().into_iter()
+        .filter(|call| matches!(call, BackendCall::TriggerTyping(..)))
+        .collect::<Vec<_>>();
+
+    assert_eq!(typing(&system), vec![
+        BackendCall::TriggerTyping(1, Id::new(CHANNEL)),
+        BackendCall::TriggerTyping(1, Id::new(THREAD)),
+    ]);
+
+    // Typed long enough ago that it's about to run out
+    let earlier = Instant::now().checked_sub(Duration::from_secs(8)).unwrap();
+    system.manager.typing_cache.put(Id::new(CHANNEL), earlier);
+    system.user_types_in(CHANNEL).await;
+
+    assert_eq!(typing(&system).len(), 3);
+}
+
+#[tokio::test]
+async fn does_not_mirror_typing_without_a_latch() {
+    let mut latched = RecordedSystem::new("recorded-typing-unlatched", LATCH_CONFIG);
+    latched.user_types_in(CHANNEL).await;
+
+    let mut unlatched = RecordedSystem::new("recorded-typing-no-autoproxy", "");
+    unlatched.user_sends("b:hi").await;
+    unlatched.user_types_in(CHANNEL).await;
+
+    for system in [latched, unlatched] {
+        assert!(!system.recording.calls().iter().any(|call| matches!(call, BackendCall::TriggerTyping(..))));
+        assert!(system.manager.typing_cache.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn forwards_pings_only_when_configured() {
+    let mut system = RecordedSystem::new("recorded-ping-off", "");
+    system.pings(OTHER_USER, 1, "hey bob").await;
+    assert!(system.recording.calls().is_empty());
+
+    let mut system = RecordedSystem::new("recorded-ping-on", "forward_pings = true");
+    system.pings(OTHER_USER, 1, "hey bob").await;
+
+    let calls = system.recording.calls();
+    let [BackendCall::SendDirectMessage(1, user_id, notification)] = calls.as_slice() else {
+        panic!("Unexpected calls {calls:?}")
+    };
+
+    assert_eq!(*user_id, Id::new(REFERENCE_USER));
+    assert!(notification.starts_with(&format!("user mentioned Bob in <#{CHANNEL}>: https://discord.com/channels/{SERVER}/{CHANNEL}/")));
+    assert!(notification.ends_with("\n> hey bob"));
+}
+
+#[tokio::test]
+async fn does_not_forward_members_pinging_each_other() {
+    let mut system = RecordedSystem::new("recorded-ping-member", "forward_pings = true");
+
+    // Alice's proxied message mentioning Bob
+    system.pings(200, 1, "hey bob").await;
+
+    assert!(system.recording.calls().is_empty());
+}
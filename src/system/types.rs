@@ -1,6 +1,17 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
 pub use twilight_model::channel::Message as TwiMessage;
+use crate::config::{MemberName, PresenceMode};
+pub use twilight_gateway::Session;
+use twilight_http::request::channel::reaction::RequestReactionType;
+use twilight_model::channel::message::ReactionType;
+use twilight_model::gateway::GatewayReaction;
 use twilight_model::gateway::payload::incoming::MessageUpdate as PartialMessage;
-use twilight_model::id::marker::{ChannelMarker, MessageMarker, UserMarker, GuildMarker};
+use twilight_model::id::marker::{ChannelMarker, EmojiMarker, MessageMarker, UserMarker, GuildMarker, WebhookMarker};
 use twilight_model::id::Id;
 use twilight_model::util::Timestamp;
 
@@ -9,38 +20,129 @@ pub type MessageId = Id<MessageMarker>;
 pub type ChannelId = Id<ChannelMarker>;
 pub type ServerId = Id<GuildMarker>;
 pub type UserId = Id<UserMarker>;
+pub type EmojiId = Id<EmojiMarker>;
+pub type WebhookId = Id<WebhookMarker>;
 pub type FullMessage = TwiMessage;
 
 pub type Status = twilight_model::gateway::presence::Status;
 
+// Discord's limit on message content
+pub const MAX_CONTENT_LENGTH: usize = 2000;
+
+impl From<&PresenceMode> for Status {
+    fn from(value: &PresenceMode) -> Self {
+        match value {
+            PresenceMode::Online => Status::Online,
+            PresenceMode::Busy => Status::DoNotDisturb,
+            PresenceMode::Idle => Status::Idle,
+            PresenceMode::Invisible => Status::Invisible,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum LatchScope {
+    Global,
+    Server(ServerId),
+    Channel(ChannelId),
+}
+
+impl fmt::Display for LatchScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LatchScope::Global => write!(f, "global"),
+            LatchScope::Server(server_id) => write!(f, "server {server_id}"),
+            LatchScope::Channel(channel_id) => write!(f, "channel {channel_id}"),
+        }
+    }
+}
+
+// Reads back the format written by Display
+impl FromStr for LatchScope {
+    type Err = String;
+
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope.split_once(' ') {
+            None if scope == "global" => Ok(LatchScope::Global),
+            Some(("server", server_id)) => server_id.parse()
+                .map(LatchScope::Server)
+                .map_err(|_| format!("Invalid server id {server_id}")),
+            Some(("channel", channel_id)) => channel_id.parse()
+                .map(LatchScope::Channel)
+                .map_err(|_| format!("Invalid channel id {channel_id}")),
+            _ => Err(format!("Unknown latch scope {scope}")),
+        }
+    }
+}
+
+pub type LatchState = HashMap<LatchScope, (MemberId, Timestamp)>;
+
 #[derive(Clone)]
 pub enum Message {
     Complete(FullMessage, MemberId),
-    Partial(PartialMessage, MemberId),
+    Partial(Box<PartialMessage>, MemberId),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Emoji {
+    Unicode(String),
+    Custom(EmojiId, Option<String>),
+}
+
+impl Emoji {
+    pub fn as_request(&self) -> RequestReactionType<'_> {
+        match self {
+            Emoji::Unicode(name) => RequestReactionType::Unicode { name },
+            Emoji::Custom(id, name) => RequestReactionType::Custom { id: *id, name: name.as_deref() },
+        }
+    }
+}
+
+impl From<ReactionType> for Emoji {
+    fn from(value: ReactionType) -> Self {
+        match value {
+            ReactionType::Unicode { name } => Emoji::Unicode(name),
+            ReactionType::Custom { animated: _, id, name } => Emoji::Custom(id, name),
+        }
+    }
+}
+
+/// Where a closed gateway connection left off, so the next one can resume instead of identifying.
+/// Resuming skips the ready event, so the bot's user comes along too.
+#[derive(Clone)]
+pub struct GatewayResume {
+    pub session: Session,
+    pub user_id: UserId,
 }
 
 pub type MessageEvent = (Timestamp, Message);
-pub type ReactionEvent = (Timestamp, ());
-pub type CommandEvent = (Timestamp, ());
+pub type ReactionEvent = (GatewayReaction, MemberId);
 
 pub enum SystemEvent {
     // Process of operation
     GatewayConnected(MemberId, UserId),
     GatewayError(MemberId, String),
-    GatewayClosed(MemberId),
+    GatewayClosed(MemberId, Option<GatewayResume>),
+    GatewayFailed(MemberId, String),
+    ReconnectGateway(MemberId, Option<GatewayResume>),
     RefetchMessage(MemberId, MessageId, ChannelId),
+    MemberPinged(MemberId, FullMessage),
     UpdateClientStatus(MemberId),
 
     // User event handling
     NewMessage(Timestamp, FullMessage, MemberId),
     EditedMessage(MessageEvent),
-    NewReaction(ReactionEvent),
+    NewReaction(Box<ReactionEvent>),
+    UserTyping(Option<ServerId>, ChannelId),
 
     // Command handling
-    NewCommand(CommandEvent),
+    NewCommand(SystemThreadCommand),
 
     // Autoproxy
-    AutoproxyTimeout(Timestamp),
+    AutoproxyTimeout(LatchScope, Timestamp),
+    SetLatch(LatchScope, MemberName),
+    ClearLatch(LatchScope),
+    ClearAllLatches,
 }
 
 pub enum SystemThreadCommand {
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use twilight_model::channel::message::MessageType;
+
+use super::backend::ProxyBackend;
+use super::bot::{Bot, BotError};
+use super::types::*;
+
+// Starts the line linking a proxied reply back to the message it replies to
+const REPLY_LINE_PREFIX: &str = "-# ↪ ";
+
+/// Proxies as a member through channel webhooks, with everything else going through the
+/// system's one listener bot
+pub struct WebhookBackend {
+    username: String,
+    avatar_url: Option<String>,
+    listener: Arc<Bot>,
+}
+
+impl WebhookBackend {
+    pub fn new(member: &crate::config::Member, listener: &Arc<Bot>) -> Self {
+        Self {
+            username: member.display_name.as_ref().unwrap_or(&member.name).clone(),
+            avatar_url: member.avatar_url.clone(),
+            listener: listener.clone(),
+        }
+    }
+
+    pub async fn reconnect_listener(&self, resume: Option<GatewayResume>) {
+        self.listener.reconnect(resume).await
+    }
+}
+
+impl ProxyBackend for WebhookBackend {
+    type Error = BotError;
+
+    // Webhooks have no presence or nickname of their own
+    async fn set_status(&self, _status: Status) {}
+    async fn set_nick(&self, _server_id: ServerId, _nick: String) {}
+
+    async fn resend_message(&self, message_id: MessageId, channel_id: ChannelId) {
+        self.listener.resend_message(message_id, channel_id).await
+    }
+
+    async fn fetch_recent_channel_messages(&self, channel_id: ChannelId) -> Result<Vec<FullMessage>, BotError> {
+        self.listener.fetch_recent_channel_messages(channel_id).await
+    }
+
+    async fn trigger_typing(&self, channel_id: ChannelId) -> Result<(), BotError> {
+        self.listener.trigger_typing(channel_id).await
+    }
+
+    async fn send_message(&self, channel_id: ChannelId, content: &str) -> Result<FullMessage, BotError> {
+        self.listener.send_message(channel_id, content).await
+    }
+
+    async fn send_direct_message(&self, user_id: UserId, content: &str) -> Result<FullMessage, BotError> {
+        self.listener.send_direct_message(user_id, content).await
+    }
+
+    async fn duplicate_message(&self, message: &TwiMessage, content: &str) -> Result<TwiMessage, BotError> {
+        // Webhooks can't reply, so link back to the message being replied to instead
+        let reply_line = match (message.kind, message.referenced_message.as_ref()) {
+            (MessageType::Reply, Some(referenced)) => Some(format!(
+                "{REPLY_LINE_PREFIX}<@{}> https://discord.com/channels/{}/{}/{}",
+                referenced.author.id,
+                message.guild_id.map_or("@me".to_string(), |server_id| server_id.to_string()),
+                referenced.channel_id,
+                referenced.id,
+            )),
+            _ => None,
+        };
+
+        let content = with_reply_line(reply_line.as_deref(), content);
+        self.listener.execute_webhook(message, &content, &self.username, self.avatar_url.as_deref()).await
+    }
+
+    async fn edit_message(&self, channel_id: ChannelId, message_id: MessageId, new_content: String) -> Result<FullMessage, BotError> {
+        // Edits only carry what was typed, so keep the reply link the message was sent with
+        let existing = self.listener.fetch_webhook_message(channel_id, message_id).await?;
+        let reply_line = existing.content.lines().next().filter(|line| line.starts_with(REPLY_LINE_PREFIX));
+
+        let new_content = with_reply_line(reply_line, &new_content);
+        self.listener.edit_webhook_message(channel_id, message_id, new_content).await
+    }
+
+    async fn delete_message(&self, channel_id: ChannelId, message_id: MessageId) -> Result<(), BotError> {
+        self.listener.delete_message(channel_id, message_id).await
+    }
+
+    async fn react_message(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji) -> Result<(), BotError> {
+        self.listener.react_message(channel_id, message_id, emoji).await
+    }
+
+    async fn unreact_message(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji) -> Result<(), BotError> {
+        self.listener.unreact_message(channel_id, message_id, emoji).await
+    }
+
+    async fn remove_reaction(&self, channel_id: ChannelId, message_id: MessageId, emoji: &Emoji, user_id: UserId) -> Result<(), BotError> {
+        self.listener.remove_reaction(channel_id, message_id, emoji, user_id).await
+    }
+}
+
+// Leaves the reply line off if there isn't room for it
+fn with_reply_line(reply_line: Option<&str>, content: &str) -> String {
+    match reply_line {
+        Some(reply_line) if reply_line.chars().count() + 1 + content.chars().count() <= MAX_CONTENT_LENGTH => format!("{reply_line}\n{content}"),
+        _ => content.to_string(),
+    }
+}
@@ -0,0 +1,305 @@
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::{Color, Print, Stylize},
+    terminal::{self, Clear, ClearType, DisableLineWrap, EnableLineWrap, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::{collections::HashMap, io::{self, IsTerminal, Write}, time::Duration};
+
+use crate::{config, system::{LatchScope, SystemEvent, SystemThreadCommand}, SystemState, UiState};
+
+// Used for systems that don't set a ui_color, in order of system name
+const FALLBACK_PALETTE: [Color; 6] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Red,
+];
+
+pub enum UiAction {
+    None,
+    Redraw,
+    Quit,
+    Command(String, SystemThreadCommand),
+    Event(String, SystemEvent),
+}
+
+pub fn enter() -> io::Result<()> {
+    terminal::enable_raw_mode()?;
+    crossterm::execute!(io::stdout(), EnterAlternateScreen, DisableLineWrap, Hide)
+}
+
+pub fn leave() -> io::Result<()> {
+    crossterm::execute!(io::stdout(), Show, EnableLineWrap, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()
+}
+
+/// Waits up to `timeout` for a key press and applies it to the UI state, returning anything
+/// the caller needs to act on
+pub fn poll_input(ui_state: &mut UiState, config: &config::Config, timeout: Duration) -> io::Result<UiAction> {
+    if !event::poll(timeout)? {
+        return Ok(UiAction::None)
+    }
+
+    match event::read()? {
+        Event::Key(key) if key.kind == KeyEventKind::Press => Ok(handle_key(ui_state, config, key)),
+        Event::Resize(_, _) => Ok(UiAction::Redraw),
+        _ => Ok(UiAction::None),
+    }
+}
+
+fn handle_key(ui_state: &mut UiState, config: &config::Config, key: KeyEvent) -> UiAction {
+    let page = log_space(ui_state).max(1);
+
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return UiAction::Quit,
+        KeyCode::Char('q') => return UiAction::Quit,
+
+        KeyCode::Tab | KeyCode::Right => select_system(ui_state, 1),
+        KeyCode::BackTab | KeyCode::Left => select_system(ui_state, -1),
+        KeyCode::Char('j') => select_member(ui_state, 1),
+        KeyCode::Char('k') => select_member(ui_state, -1),
+
+        KeyCode::Up => scroll_logs(ui_state, 1),
+        KeyCode::Down => scroll_logs(ui_state, -1),
+        KeyCode::PageUp => scroll_logs(ui_state, page as isize),
+        KeyCode::PageDown => scroll_logs(ui_state, -(page as isize)),
+        KeyCode::Home => scroll_logs(ui_state, isize::MAX),
+        KeyCode::End => ui_state.log_scroll = 0,
+
+        KeyCode::Char('f') => {
+            ui_state.log_filter = match ui_state.log_filter {
+                Some(_) => None,
+                None => ui_state.selected_system_name().cloned(),
+            };
+            ui_state.log_scroll = 0;
+        },
+
+        KeyCode::Char('r') => return system_command(ui_state, SystemThreadCommand::Restart),
+        KeyCode::Char('l') => return system_command(ui_state, SystemThreadCommand::ReloadConfig),
+        KeyCode::Char('s') => return system_command(ui_state, SystemThreadCommand::ShutdownSystem),
+
+        KeyCode::Char('a') => return latch_selected_member(ui_state, config),
+        KeyCode::Char('x') => return system_event(ui_state, SystemEvent::ClearAllLatches),
+
+        _ => return UiAction::None,
+    }
+
+    UiAction::Redraw
+}
+
+fn system_command(ui_state: &UiState, command: SystemThreadCommand) -> UiAction {
+    match ui_state.selected_system_name() {
+        Some(name) => UiAction::Command(name.clone(), command),
+        None => UiAction::None,
+    }
+}
+
+fn system_event(ui_state: &UiState, event: SystemEvent) -> UiAction {
+    match ui_state.selected_system_name() {
+        Some(name) => UiAction::Event(name.clone(), event),
+        None => UiAction::None,
+    }
+}
+
+// The TUI isn't in any channel or server, so it can only latch system-wide
+fn can_latch_globally(ui_state: &UiState, config: &config::Config) -> bool {
+    let system = ui_state.selected_system_name().and_then(|name| config.systems.get(name));
+    matches!(system.and_then(|system| system.autoproxy.as_ref()), Some(config::AutoproxyConfig::Latch { scope: config::AutoProxyScope::Global, .. }))
+}
+
+fn latch_selected_member(ui_state: &mut UiState, config: &config::Config) -> UiAction {
+    if !can_latch_globally(ui_state, config) {
+        if let Some(name) = ui_state.selected_system_name().cloned() {
+            ui_state.push_log(&name, "Members can only be latched from here with global latch autoproxy".to_string());
+        }
+        return UiAction::Redraw
+    }
+
+    let member_name = match ui_state.selected_system_name().and_then(|name| ui_state.systems.get(name)) {
+        Some(SystemState::Running(members)) => members.keys().nth(ui_state.selected_member).cloned(),
+        _ => None,
+    };
+
+    match member_name {
+        Some(member_name) => system_event(ui_state, SystemEvent::SetLatch(LatchScope::Global, member_name)),
+        None => UiAction::None,
+    }
+}
+
+fn select_system(ui_state: &mut UiState, offset: isize) {
+    let count = ui_state.systems.len() as isize;
+    if count == 0 {
+        return
+    }
+
+    ui_state.selected_system = (ui_state.selected_system as isize + offset).rem_euclid(count) as usize;
+    ui_state.selected_member = 0;
+
+    // Keep filtering on whichever system is selected
+    if ui_state.log_filter.is_some() {
+        ui_state.log_filter = ui_state.selected_system_name().cloned();
+        ui_state.log_scroll = 0;
+    }
+}
+
+fn select_member(ui_state: &mut UiState, offset: isize) {
+    let count = match ui_state.selected_system_name().and_then(|name| ui_state.systems.get(name)) {
+        Some(SystemState::Running(members)) => members.len() as isize,
+        _ => return,
+    };
+
+    if count == 0 {
+        return
+    }
+
+    ui_state.selected_member = (ui_state.selected_member as isize + offset).clamp(0, count - 1) as usize;
+}
+
+fn scroll_logs(ui_state: &mut UiState, offset: isize) {
+    let max_scroll = ui_state.filtered_log_count().saturating_sub(log_space(ui_state));
+    ui_state.log_scroll = (ui_state.log_scroll as isize).saturating_add(offset).clamp(0, max_scroll as isize) as usize;
+}
+
+fn status_lines(ui_state: &UiState) -> usize {
+    (ui_state.systems.len() * 2) + ui_state.systems.values().map(|system| match system {
+        SystemState::Running(members) => members.len(),
+        SystemState::Reloading => 1,
+        SystemState::Restarting => 1,
+        SystemState::Shutdown => 1,
+    } ).sum::<usize>() + ui_state.latches.values().map(|latches| latches.len()).sum::<usize>()
+}
+
+fn log_space(ui_state: &UiState) -> usize {
+    let (_, height) = terminal::size().unwrap_or((80, 24));
+
+    // Status lines, then the separator above the logs and the key help below them
+    (height as usize).saturating_sub(status_lines(ui_state) + 2)
+}
+
+pub fn draw(ui_state: &UiState, config: &config::Config) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let (width, _) = terminal::size()?;
+    let width = width as usize;
+    let colors = system_colors(config);
+
+    let mut lines = Vec::<String>::new();
+
+    for (index, (name, state)) in ui_state.systems.iter().enumerate() {
+        let selected = index == ui_state.selected_system;
+        let header = format!("{} {}", if selected { ">" } else { " " }, name);
+        lines.push(colorize(truncate(header, width), colors.get(name)));
+
+        match state {
+            SystemState::Shutdown => lines.push("  - [System stopped]".to_string()),
+            SystemState::Reloading => lines.push("  - [System reloading]".to_string()),
+            SystemState::Restarting => lines.push("  - [System restarting]".to_string()),
+            SystemState::Running(members) => for (member_index, (name, state)) in members.iter().enumerate() {
+                let line = if state.failed {
+                    format!("  - {name} (failed)")
+                } else if !state.connected {
+                    format!("  - {name} (connecting)")
+                } else if state.autoproxied {
+                    format!("  - {name} (autoproxy)")
+                } else {
+                    format!("  - {name}")
+                };
+
+                if selected && member_index == ui_state.selected_member {
+                    lines.push(truncate(line, width).reverse().to_string());
+                } else {
+                    lines.push(truncate(line, width));
+                }
+            },
+        }
+
+        if let Some(latches) = ui_state.latches.get(name) {
+            for (scope, member_name) in latches {
+                lines.push(truncate(format!("  @ {scope}: {member_name}"), width));
+            }
+        }
+
+        lines.push(String::new());
+    }
+
+    let title = match (&ui_state.log_filter, ui_state.log_scroll) {
+        (Some(name), 0) => format!("-- logs: {name} "),
+        (Some(name), scroll) => format!("-- logs: {name} (+{scroll}) "),
+        (None, 0) => String::new(),
+        (None, scroll) => format!("-- logs (+{scroll}) "),
+    };
+    lines.push(format!("{:-<width$}", truncate(title, width)));
+
+    let logs: Vec<_> = ui_state.filtered_logs().collect();
+    let end = logs.len().saturating_sub(ui_state.log_scroll);
+    let start = end.saturating_sub(log_space(ui_state));
+
+    for (system_name, log) in &logs[start..end] {
+        lines.push(colorize(truncate(format!("{system_name:>8.8}: {log}"), width), colors.get(system_name)));
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        queue!(stdout, MoveTo(0, row as u16), Print(line), Clear(ClearType::UntilNewLine))?;
+    }
+    queue!(stdout, MoveTo(0, lines.len() as u16), Clear(ClearType::FromCursorDown))?;
+
+    let (_, height) = terminal::size()?;
+    let latch_help = if can_latch_globally(ui_state, config) { "a latch  " } else { "" };
+    let help = format!("tab/←→ system  j/k member  {latch_help}x unlatch  ↑↓/pgup/pgdn/home/end scroll  f filter  r restart  l reload  s shutdown  q quit");
+    queue!(stdout, MoveTo(0, height.saturating_sub(1)), Print(truncate(help, width).dim()))?;
+
+    stdout.flush()
+}
+
+fn truncate(text: String, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text
+    }
+
+    text.chars().take(width).collect()
+}
+
+fn system_colors(config: &config::Config) -> HashMap<String, Color> {
+    // Leave output alone when it's not going to a terminal, or the user asked us to
+    if !io::stdout().is_terminal() || std::env::var_os("NO_COLOR").is_some() {
+        return HashMap::new()
+    }
+
+    let mut system_names: Vec<&String> = config.systems.keys().collect();
+    system_names.sort();
+
+    system_names.into_iter().enumerate().map(|(index, name)| {
+        let color = config.systems[name].ui_color.as_deref()
+            .and_then(parse_color)
+            .unwrap_or(FALLBACK_PALETTE[index % FALLBACK_PALETTE.len()]);
+
+        (name.clone(), color)
+    }).collect()
+}
+
+fn parse_color(color: &str) -> Option<Color> {
+    if let Some(hex) = color.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None
+        }
+
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        return Some(Color::Rgb {
+            r: (value >> 16) as u8,
+            g: (value >> 8) as u8,
+            b: value as u8,
+        })
+    }
+
+    Color::try_from(color).ok()
+}
+
+fn colorize(text: String, color: Option<&Color>) -> String {
+    match color {
+        Some(color) => text.with(*color).to_string(),
+        None => text,
+    }
+}